@@ -0,0 +1,646 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::capture::CaptureReader;
+use crate::metrics::MetricsWriter;
+use crate::trace::{Outage, Trace};
+use crate::webserver;
+
+/**
+ * LINKTYPE_* from https://www.tcpdump.org/linktypes.html
+ */
+const LINKTYPE_ETHERNET: u16 = 1;
+const LINKTYPE_RAW: u16 = 101;
+const LINKTYPE_LINUX_SLL: u16 = 113;
+
+/**
+ * TCP flags
+ */
+const TCP_FIN: u8 = 0x01;
+const TCP_SYN: u8 = 0x02;
+const TCP_RST: u8 = 0x04;
+const TCP_ACK: u8 = 0x10;
+
+/**
+ * Duplicate ACKs that trigger a fast retransmit
+ */
+const DUP_ACK_THRESHOLD: u32 = 3;
+
+/**
+ * Analysis settings
+ */
+pub struct AnalyzeConfig {
+    /// only connections to/from this port are analyzed
+    pub port: u16,
+    /// interface of the capture to analyze, the first one if unset
+    pub interface: Option<String>,
+    /// unix time of the trace start, taken from the capture if unset
+    pub trace_start: Option<f64>,
+    /// bin size for throughput in s
+    pub interval: f64,
+    /// time before an outage its throughput is averaged over in s
+    pub baseline: f64,
+    /// share of the pre-outage throughput that counts as recovered
+    pub threshold: f64,
+}
+
+impl Default for AnalyzeConfig {
+    /// same defaults as on the command line
+    fn default() -> Self {
+        Self {
+            port: webserver::PORT,
+            interface: None,
+            trace_start: None,
+            interval: 0.1,
+            baseline: 2.0,
+            threshold: 0.9,
+        }
+    }
+}
+
+/**
+ * Address and port of one end of a connection
+ */
+type Endpoint = (IpAddr, u16);
+
+/**
+ * Header fields of a TCP segment
+ */
+struct Segment {
+    src: Endpoint,
+    dst: Endpoint,
+    seq: u32,
+    ack: u32,
+    flags: u8,
+    /// payload length from the IP header
+    /// so it is correct for truncated packets
+    len: u32,
+}
+
+/**
+ * Parse a TCP segment out of a captured packet
+ * anything else yields None
+ * @param linktype  Link type of the capture interface
+ * @param data      Captured bytes
+ */
+fn parse_segment(linktype: u16, data: &[u8]) -> Option<Segment> {
+    let ip = match linktype {
+        LINKTYPE_ETHERNET => {
+            let mut offset = 12;
+            let mut ethertype = u16::from_be_bytes([*data.get(offset)?, *data.get(offset + 1)?]);
+            // VLAN tags
+            while ethertype == 0x8100 || ethertype == 0x88a8 {
+                offset += 4;
+                ethertype = u16::from_be_bytes([*data.get(offset)?, *data.get(offset + 1)?]);
+            }
+            data.get(offset + 2..)?
+        },
+        LINKTYPE_LINUX_SLL => data.get(16..)?,
+        LINKTYPE_RAW => data,
+        _ => return None,
+    };
+
+    // (src, dst, transport header, transport length)
+    let (src, dst, tcp, tcp_len) = match ip.first()? >> 4 {
+        4 => {
+            let header_len = ((ip.first()? & 0x0f) as usize) * 4;
+            if *ip.get(9)? != libc::IPPROTO_TCP as u8 {
+                return None;
+            }
+            let total_len = u16::from_be_bytes([*ip.get(2)?, *ip.get(3)?]) as usize;
+            let src: [u8; 4] = ip.get(12..16)?.try_into().ok()?;
+            let dst: [u8; 4] = ip.get(16..20)?.try_into().ok()?;
+            (IpAddr::V4(Ipv4Addr::from(src)), IpAddr::V4(Ipv4Addr::from(dst)),
+                ip.get(header_len..)?, total_len.checked_sub(header_len)?)
+        },
+        6 => {
+            // extension headers are not followed
+            if *ip.get(6)? != libc::IPPROTO_TCP as u8 {
+                return None;
+            }
+            let payload_len = u16::from_be_bytes([*ip.get(4)?, *ip.get(5)?]) as usize;
+            let src: [u8; 16] = ip.get(8..24)?.try_into().ok()?;
+            let dst: [u8; 16] = ip.get(24..40)?.try_into().ok()?;
+            (IpAddr::V6(Ipv6Addr::from(src)), IpAddr::V6(Ipv6Addr::from(dst)),
+                ip.get(40..)?, payload_len)
+        },
+        _ => return None,
+    };
+
+    let u16_at = |offset: usize| Some(u16::from_be_bytes([*tcp.get(offset)?, *tcp.get(offset + 1)?]));
+    let u32_at = |offset: usize| Some(u32::from_be_bytes(tcp.get(offset..offset + 4)?.try_into().ok()?));
+    let header_len = ((tcp.get(12)? >> 4) as usize) * 4;
+
+    Some(Segment {
+        src: (src, u16_at(0)?),
+        dst: (dst, u16_at(2)?),
+        seq: u32_at(4)?,
+        ack: u32_at(8)?,
+        flags: *tcp.get(13)?,
+        len: tcp_len.checked_sub(header_len)? as u32,
+    })
+}
+
+/**
+ * a comes after b in sequence space
+ */
+fn seq_after(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) > 0
+}
+
+/**
+ * State of one direction of a connection
+ */
+#[derive(Clone, Copy, Default)]
+struct HalfConnection {
+    /// highest sequence number sent + 1
+    next_seq: Option<u32>,
+    /// highest cumulative ACK sent
+    last_ack: Option<u32>,
+    /// duplicates of last_ack sent
+    dup_acks: u32,
+    /// last segment start that was retransmitted
+    retransmitted: Option<u32>,
+}
+
+/**
+ * State per direction of every connection, keyed by (source, destination)
+ */
+type Connections = HashMap<(Endpoint, Endpoint), HalfConnection>;
+
+/**
+ * What happened on the analyzed connections
+ * times are seconds since the start of the trace
+ */
+#[derive(Default)]
+struct Timeline {
+    /// retransmitted segments and whether they were caused by an RTO
+    retransmissions: Vec<(f64, bool)>,
+    dup_acks: Vec<f64>,
+    /// newly acknowledged bytes
+    deliveries: Vec<(f64, u64)>,
+    /// time of the last packet
+    end: f64,
+}
+
+impl Timeline {
+    /**
+     * Follow a TCP segment through its connection
+     * @param time     Time of the segment
+     * @param segment  Parsed segment
+     * @param halves   Connection state per direction
+     */
+    fn add(
+        &mut self,
+        time: f64,
+        segment: &Segment,
+        halves: &mut Connections
+    ) {
+        let reverse = halves.get(&(segment.dst, segment.src)).copied().unwrap_or_default();
+        let half = halves.entry((segment.src, segment.dst)).or_default();
+        let control = segment.flags & (TCP_SYN | TCP_FIN | TCP_RST) != 0;
+
+        // ACKs for the data of the other direction
+        if segment.flags & TCP_ACK != 0 {
+            match half.last_ack {
+                Some(last) if seq_after(segment.ack, last) => {
+                    self.deliveries.push((time, segment.ack.wrapping_sub(last) as u64));
+                    half.last_ack = Some(segment.ack);
+                    half.dup_acks = 0;
+                },
+                Some(last) if segment.ack == last && segment.len == 0 && !control
+                    && reverse.next_seq.is_some_and(|next| seq_after(next, last)) => {
+                    half.dup_acks += 1;
+                    self.dup_acks.push(time);
+                },
+                Some(_) => (),
+                None => half.last_ack = Some(segment.ack),
+            }
+        }
+
+        // data
+        if segment.len > 0 {
+            let end = segment.seq.wrapping_add(segment.len);
+            match half.next_seq {
+                Some(next) if seq_after(next, segment.seq) => {
+                    // retransmission of the first unacknowledged segment without
+                    // enough duplicate ACKs for a fast retransmit or a second time
+                    let rto = reverse.last_ack == Some(segment.seq)
+                        && (reverse.dup_acks < DUP_ACK_THRESHOLD
+                            || half.retransmitted == Some(segment.seq));
+                    self.retransmissions.push((time, rto));
+                    half.retransmitted = Some(segment.seq);
+                    if seq_after(end, next) {
+                        half.next_seq = Some(end);
+                    }
+                },
+                _ => half.next_seq = Some(end),
+            }
+        }
+
+        self.end = self.end.max(time);
+    }
+
+    /**
+     * Acknowledged bytes in [from, to)
+     * deliveries are in capture order i.e. sorted by time
+     */
+    fn delivered(&self, from: f64, to: f64) -> u64 {
+        let first = self.deliveries.partition_point(|(time, _)| *time < from);
+        let last = self.deliveries.partition_point(|(time, _)| *time < to);
+        self.deliveries[first..last.max(first)].iter()
+            .map(|(_, bytes)| bytes)
+            .sum()
+    }
+}
+
+/**
+ * Results for a single outage
+ */
+#[derive(Serialize)]
+pub struct OutageReport {
+    pub outage: usize,
+    /// seconds since the start of the trace
+    pub start: f64,
+    /// seconds since the start of the trace
+    pub end: f64,
+    /// uplink, downlink or both
+    pub direction: &'static str,
+    /// retransmitted segments until the next outage
+    pub retransmissions: usize,
+    /// duplicate ACKs until the next outage
+    pub dup_acks: usize,
+    /// retransmissions by timeout until the next outage
+    pub rto_events: usize,
+    /// seconds from the end of the outage until new data was acknowledged
+    /// before the next outage
+    pub first_delivery: Option<f64>,
+    /// throughput before the outage in mbit/s
+    /// not reaching back into the previous outage
+    pub pre_outage_rate: Option<f64>,
+    /// seconds from the end of the outage until the throughput
+    /// is back to the pre-outage level
+    pub recovery_time: Option<f64>,
+}
+
+/**
+ * Results of a capture analysis
+ */
+pub struct CaptureAnalysis {
+    /// name of the analyzed interface
+    pub interface: String,
+    /// analyzed TCP connections
+    pub connections: usize,
+    /// results per outage
+    pub outages: Vec<OutageReport>,
+}
+
+/**
+ * Evaluate one outage
+ * @param index     Number of the outage
+ * @param outage    The outage
+ * @param since     End of the previous outage
+ * @param until     Start of the next outage
+ * @param timeline  Events of the connections
+ * @param config    Analysis settings
+ */
+fn report_outage(
+    index: usize,
+    outage: &Outage,
+    since: f64,
+    until: f64,
+    timeline: &Timeline,
+    config: &AnalyzeConfig
+) -> OutageReport {
+    let in_window = |time: f64| time >= outage.start && time < until;
+    let mbit = |bytes: u64, seconds: f64| bytes as f64 * 8.0 / 1_000_000.0 / seconds;
+
+    // deliveries after the next outage started belong to that one
+    let first_delivery = timeline.deliveries.iter()
+        .find(|(time, _)| *time >= outage.end)
+        .map(|(time, _)| *time)
+        .filter(|time| *time < until)
+        .map(|time| time - outage.end);
+
+    // average over the baseline, or what there is of it
+    // since the previous outage
+    let baseline_start = (outage.start - config.baseline).max(since);
+    let pre_outage_rate = Some(outage.start - baseline_start)
+        .filter(|seconds| *seconds > 0.0)
+        .map(|seconds| mbit(timeline.delivered(baseline_start, outage.start), seconds))
+        .filter(|rate| *rate > 0.0);
+
+    // first bin after the outage reaching the pre-outage rate
+    let recovery_time = pre_outage_rate.and_then(|pre_rate| {
+        let mut bin = outage.end;
+        while bin < until.min(timeline.end) {
+            let rate = mbit(timeline.delivered(bin, bin + config.interval), config.interval);
+            bin += config.interval;
+            if rate >= pre_rate * config.threshold {
+                return Some(bin - outage.end);
+            }
+        }
+        None
+    });
+
+    OutageReport {
+        outage: index,
+        start: outage.start,
+        end: outage.end,
+        direction: outage.directions(),
+        retransmissions: timeline.retransmissions.iter()
+            .filter(|(time, _)| in_window(*time))
+            .count(),
+        dup_acks: timeline.dup_acks.iter()
+            .filter(|time| in_window(**time))
+            .count(),
+        rto_events: timeline.retransmissions.iter()
+            .filter(|(time, rto)| *rto && in_window(*time))
+            .count(),
+        first_delivery,
+        pre_outage_rate,
+        recovery_time,
+    }
+}
+
+/**
+ * Analyze how TCP got through every outage of a trace
+ * Reads the capture of a run and reports per outage the
+ * retransmissions, duplicate ACKs and RTOs until the next outage,
+ * the time until new data was acknowledged after the outage and
+ * the time until the throughput was back to the pre-outage level
+ * @param capture_file  pcapng capture of the run
+ * @param outages       Outages of the trace the run was played with
+ * @param config        Analysis settings
+ */
+pub fn analyze_capture(
+    capture_file: &str,
+    outages: &[Outage],
+    config: &AnalyzeConfig
+) -> Result<CaptureAnalysis, String> {
+    let mut reader = CaptureReader::open(capture_file)?;
+    let trace_start = config.trace_start.or(reader.trace_start)
+        .ok_or(format!("{} has no trace start, pass --trace-start", capture_file))?;
+
+    let mut timeline = Timeline::default();
+    let mut halves = Connections::new();
+    let mut interface: Option<usize> = None;
+    while let Some(packet) = reader.next_packet()? {
+        // pick the interface once its description is known
+        let selected = match (interface, &config.interface) {
+            (Some(selected), _) => selected,
+            (None, Some(name)) => reader.interfaces.iter()
+                .position(|interface| interface.name.as_deref() == Some(name.as_str()))
+                .ok_or(format!("No interface {} in {}", name, capture_file))?,
+            (None, None) => 0,
+        };
+        interface = Some(selected);
+        if packet.interface != selected {
+            continue;
+        }
+
+        let linktype = reader.interfaces[packet.interface].linktype;
+        let Some(segment) = parse_segment(linktype, &packet.data) else {
+            continue;
+        };
+        if segment.src.1 != config.port && segment.dst.1 != config.port {
+            continue;
+        }
+        timeline.add(packet.timestamp - trace_start, &segment, &mut halves);
+    }
+
+    Ok(CaptureAnalysis {
+        interface: interface
+            .and_then(|interface| reader.interfaces.get(interface))
+            .and_then(|interface| interface.name.clone())
+            .unwrap_or(String::from("?")),
+        connections: halves.len() / 2,
+        outages: outages.iter().enumerate()
+            .map(|(index, outage)| {
+                let since = index.checked_sub(1)
+                    .map(|previous| outages[previous].end)
+                    .unwrap_or(0.0);
+                let until = outages.get(index + 1)
+                    .map(|next| next.start)
+                    .unwrap_or(f64::INFINITY);
+                report_outage(index + 1, outage, since, until, &timeline, config)
+            })
+            .collect(),
+    })
+}
+
+/**
+ * Analyze a capture and print the results per outage
+ * see analyze_capture()
+ * @param capture_file  pcapng capture of the run
+ * @param trace         Trace the run was played with
+ * @param config        Analysis settings
+ * @param output        Optional file for the results, CSV, JSON Lines or JSON
+ */
+pub fn analyze(
+    capture_file: &str,
+    trace: &Trace,
+    config: &AnalyzeConfig,
+    output: Option<String>
+) -> Result<(), String> {
+    let analysis = analyze_capture(capture_file, &trace.outages(), config)?;
+    println!("[analyze] {} connection(s) on port {} captured on {}",
+        analysis.connections, config.port, analysis.interface);
+
+    let mut writer = output
+        .map(|path| MetricsWriter::create(path.as_str()))
+        .transpose()?;
+
+    println!("{:>3} {:>9} {:>9} {:>9} {:>7} {:>7} {:>4} {:>10} {:>10} {:>10}",
+        "#", "start", "end", "direction", "retrans", "dupacks", "rto",
+        "first [s]", "pre [mbit]", "recov [s]");
+    for report in analysis.outages {
+        let optional = |value: Option<f64>| value
+            .map(|value| format!("{:.3}", value))
+            .unwrap_or(String::from("-"));
+        println!("{:>3} {:>9.3} {:>9.3} {:>9} {:>7} {:>7} {:>4} {:>10} {:>10} {:>10}",
+            report.outage, report.start, report.end, report.direction,
+            report.retransmissions, report.dup_acks, report.rto_events,
+            optional(report.first_delivery), optional(report.pre_outage_rate),
+            optional(report.recovery_time));
+
+        if let Some(writer) = writer.as_mut() {
+            writer.write(&report)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVER: Endpoint = (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 8000);
+    const CLIENT: Endpoint = (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), 40000);
+
+    /**
+     * TCP header without options followed by the payload
+     */
+    fn tcp(seq: u32, ack: u32, flags: u8, payload: usize) -> Vec<u8> {
+        let mut tcp = Vec::new();
+        tcp.extend_from_slice(&8000u16.to_be_bytes());
+        tcp.extend_from_slice(&40000u16.to_be_bytes());
+        tcp.extend_from_slice(&seq.to_be_bytes());
+        tcp.extend_from_slice(&ack.to_be_bytes());
+        tcp.extend_from_slice(&[5 << 4, flags, 0, 0, 0, 0, 0, 0]);
+        tcp.resize(20 + payload, 0xaa);
+        tcp
+    }
+
+    fn ipv4(protocol: u8, transport: &[u8]) -> Vec<u8> {
+        let mut ip = vec![0x45, 0];
+        ip.extend_from_slice(&((20 + transport.len()) as u16).to_be_bytes());
+        ip.extend_from_slice(&[0, 0, 0, 0, 64, protocol, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2]);
+        ip.extend_from_slice(transport);
+        ip
+    }
+
+    fn ipv6(transport: &[u8]) -> Vec<u8> {
+        let mut ip = vec![0x60, 0, 0, 0];
+        ip.extend_from_slice(&(transport.len() as u16).to_be_bytes());
+        ip.extend_from_slice(&[libc::IPPROTO_TCP as u8, 64]);
+        ip.extend_from_slice(&Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1).octets());
+        ip.extend_from_slice(&Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 2).octets());
+        ip.extend_from_slice(transport);
+        ip
+    }
+
+    /**
+     * Ethernet frame with VLAN tags in front of the ethertype
+     */
+    fn ethernet(vlans: &[u16], ethertype: u16, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0u8; 12];
+        for tpid in vlans {
+            frame.extend_from_slice(&tpid.to_be_bytes());
+            frame.extend_from_slice(&[0, 42]);
+        }
+        frame.extend_from_slice(&ethertype.to_be_bytes());
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    fn segment(src: Endpoint, dst: Endpoint, seq: u32, ack: u32, len: u32) -> Segment {
+        Segment { src, dst, seq, ack, flags: TCP_ACK, len }
+    }
+
+    #[test]
+    fn parse_ipv4() {
+        let frame = ethernet(&[], 0x0800, &ipv4(libc::IPPROTO_TCP as u8,
+            &tcp(1000, 2000, TCP_ACK, 1448)));
+        // captured with a small snaplen, the length still comes from the IP header
+        let segment = parse_segment(LINKTYPE_ETHERNET, &frame[..80]).unwrap();
+        assert_eq!(segment.src, (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 8000));
+        assert_eq!(segment.dst, (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), 40000));
+        assert_eq!((segment.seq, segment.ack, segment.flags, segment.len), (1000, 2000, TCP_ACK, 1448));
+
+        let raw = ipv4(libc::IPPROTO_TCP as u8, &tcp(1, 2, TCP_SYN, 0));
+        assert_eq!(parse_segment(LINKTYPE_RAW, &raw).unwrap().flags, TCP_SYN);
+
+        let udp = ethernet(&[], 0x0800, &ipv4(libc::IPPROTO_UDP as u8, &[0u8; 28]));
+        assert!(parse_segment(LINKTYPE_ETHERNET, &udp).is_none());
+    }
+
+    #[test]
+    fn parse_ipv6_vlan() {
+        let frame = ethernet(&[0x88a8, 0x8100], 0x86dd, &ipv6(&tcp(7, 8, TCP_ACK | TCP_FIN, 10)));
+        let segment = parse_segment(LINKTYPE_ETHERNET, &frame).unwrap();
+        assert_eq!(segment.src.0, IpAddr::V6(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1)));
+        assert_eq!(segment.dst.0, IpAddr::V6(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 2)));
+        assert_eq!((segment.seq, segment.ack, segment.flags, segment.len), (7, 8, TCP_ACK | TCP_FIN, 10));
+
+        // cut off in the middle of the VLAN tags
+        assert!(parse_segment(LINKTYPE_ETHERNET, &frame[..16]).is_none());
+    }
+
+    #[test]
+    fn seq_after_wraps() {
+        assert!(seq_after(2, 1));
+        assert!(!seq_after(1, 2));
+        assert!(!seq_after(5, 5));
+        assert!(seq_after(10, u32::MAX - 10));
+        assert!(!seq_after(u32::MAX - 10, 10));
+    }
+
+    #[test]
+    fn fast_retransmit_and_rto() {
+        let mut timeline = Timeline::default();
+        let mut halves = Connections::new();
+        // sequence numbers wrap in the middle of the transfer
+        let isn = u32::MAX - 1500;
+        let seg = |n: u32| isn.wrapping_add(n * 1000);
+
+        timeline.add(0.0, &segment(CLIENT, SERVER, 1, isn, 0), &mut halves);
+        for n in 0..5 {
+            timeline.add(0.1, &segment(SERVER, CLIENT, seg(n), 1, 1000), &mut halves);
+        }
+        // segment 1 is lost, the others trigger duplicate ACKs
+        timeline.add(0.2, &segment(CLIENT, SERVER, 1, seg(1), 0), &mut halves);
+        for _ in 0..3 {
+            timeline.add(0.2, &segment(CLIENT, SERVER, 1, seg(1), 0), &mut halves);
+        }
+        timeline.add(0.3, &segment(SERVER, CLIENT, seg(1), 1, 1000), &mut halves);
+        // the fast retransmit is lost as well and times out
+        timeline.add(1.3, &segment(SERVER, CLIENT, seg(1), 1, 1000), &mut halves);
+        timeline.add(1.4, &segment(CLIENT, SERVER, 1, seg(5), 0), &mut halves);
+
+        assert_eq!(timeline.dup_acks, [0.2, 0.2, 0.2]);
+        assert_eq!(timeline.retransmissions, [(0.3, false), (1.3, true)]);
+        assert_eq!(timeline.deliveries, [(0.2, 1000), (1.4, 4000)]);
+        assert_eq!(timeline.delivered(0.0, 1.0), 1000);
+    }
+
+    #[test]
+    fn rto_without_dup_acks() {
+        let mut timeline = Timeline::default();
+        let mut halves = Connections::new();
+        timeline.add(0.0, &segment(CLIENT, SERVER, 1, 100, 0), &mut halves);
+        timeline.add(0.1, &segment(SERVER, CLIENT, 100, 1, 1000), &mut halves);
+        timeline.add(0.1, &segment(SERVER, CLIENT, 1100, 1, 1000), &mut halves);
+        // a single duplicate isn't enough for a fast retransmit
+        timeline.add(0.2, &segment(CLIENT, SERVER, 1, 100, 0), &mut halves);
+        timeline.add(1.1, &segment(SERVER, CLIENT, 100, 1, 1000), &mut halves);
+        // retransmitting a later segment is no timeout
+        timeline.add(1.2, &segment(SERVER, CLIENT, 1100, 1, 1000), &mut halves);
+
+        assert_eq!(timeline.dup_acks, [0.2]);
+        assert_eq!(timeline.retransmissions, [(1.1, true), (1.2, false)]);
+    }
+
+    #[test]
+    fn outage_window() {
+        // 10 mbit/s before the first outage, 5 mbit/s from 3.5s until the second one
+        let mut timeline = Timeline::default();
+        for n in 0..100 {
+            let time = n as f64 * 0.1;
+            match time {
+                t if !(2.0..6.0).contains(&t) => timeline.deliveries.push((time, 125_000)),
+                t if (3.5..5.0).contains(&t) => timeline.deliveries.push((time, 62_500)),
+                _ => (),
+            }
+            timeline.end = time;
+        }
+        let first = Outage { start: 2.0, end: 3.0, uplink: false, downlink: true };
+        let second = Outage { start: 5.0, end: 6.0, uplink: false, downlink: true };
+        let config = AnalyzeConfig { baseline: 3.0, ..AnalyzeConfig::default() };
+
+        let report = report_outage(1, &first, 0.0, second.start, &timeline, &config);
+        assert!((report.first_delivery.unwrap() - 0.5).abs() < 1e-9);
+        assert!((report.pre_outage_rate.unwrap() - 10.0).abs() < 1e-9);
+
+        // the baseline stops at the end of the previous outage
+        let report = report_outage(2, &second, first.end, f64::INFINITY, &timeline, &config);
+        assert!((report.pre_outage_rate.unwrap() - 3.75).abs() < 1e-9);
+        assert!(report.first_delivery.unwrap().abs() < 1e-9);
+
+        // deliveries after the next outage started don't count
+        let report = report_outage(1, &first, 0.0, 3.4, &timeline, &config);
+        assert_eq!(report.first_delivery, None);
+    }
+}
@@ -0,0 +1,736 @@
+use netns_rs::NetNs;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::metrics::unix_time;
+
+/**
+ * ETH_P_ALL from /include/uapi/linux/if_ether.h
+ */
+const ETH_P_ALL: u16 = 0x0003;
+
+/**
+ * LINKTYPE_ETHERNET from https://www.tcpdump.org/linktypes.html
+ */
+const LINKTYPE_ETHERNET: u16 = 1;
+
+/**
+ * pcapng block types and options
+ * https://www.ietf.org/archive/id/draft-ietf-opsawg-pcapng-02.html
+ */
+const BLOCK_SHB: u32 = 0x0A0D_0D0A;
+const BLOCK_IDB: u32 = 0x0000_0001;
+const BLOCK_ISB: u32 = 0x0000_0005;
+const BLOCK_EPB: u32 = 0x0000_0006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+const OPT_ENDOFOPT: u16 = 0;
+const OPT_COMMENT: u16 = 1;
+const SHB_USERAPPL: u16 = 4;
+const IF_NAME: u16 = 2;
+const IF_TSRESOL: u16 = 9;
+
+/**
+ * Largest packet we read, veths can do 64k with GSO
+ */
+const MAX_PACKET: usize = 256 * 1024;
+
+/**
+ * Section header comment with the unix time the trace started at
+ * followed by the time in seconds
+ */
+const TRACE_START_COMMENT: &str = "trace start ";
+
+/**
+ * Default snaplen, same as tcpdump
+ */
+pub const DEFAULT_SNAPLEN: u32 = 262_144;
+
+/**
+ * How often capture threads check if they should stop
+ */
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/**
+ * Settings of a packet capture
+ */
+#[derive(Clone, Debug)]
+pub struct CaptureConfig {
+    /// pcapng file to write
+    pub file: String,
+    /// bytes captured per packet
+    pub snaplen: u32,
+}
+
+/**
+ * Something to be written to the capture file
+ */
+enum Record {
+    /// packet captured on an interface
+    Packet {
+        interface: u32,
+        /// ns since the unix epoch
+        timestamp: u64,
+        original_len: u32,
+        data: Vec<u8>,
+    },
+    /// comment for the next packet
+    Comment {
+        /// ns since the unix epoch when it was made
+        timestamp: u64,
+        text: String,
+    },
+}
+
+/**
+ * Append a pcapng option, padded to 32 bit
+ * @param buf    Buffer to append to
+ * @param code   Option code
+ * @param value  Option value
+ */
+fn push_option(buf: &mut Vec<u8>, code: u16, value: &[u8]) {
+    buf.extend_from_slice(&code.to_ne_bytes());
+    buf.extend_from_slice(&(value.len() as u16).to_ne_bytes());
+    buf.extend_from_slice(value);
+    buf.resize(buf.len().next_multiple_of(4), 0);
+}
+
+/**
+ * Write a pcapng block
+ * @param writer      Output
+ * @param block_type  Type of the block
+ * @param body        Block body including options
+ */
+fn write_block<W: Write>(writer: &mut W, block_type: u32, body: &[u8]) -> std::io::Result<()> {
+    // type + length + body + length
+    let len = (body.len() + 12) as u32;
+    writer.write_all(&block_type.to_ne_bytes())?;
+    writer.write_all(&len.to_ne_bytes())?;
+    writer.write_all(body)?;
+    writer.write_all(&len.to_ne_bytes())
+}
+
+/**
+ * Write section header and one interface description per interface
+ * timestamps are in ns
+ * @param writer       Output
+ * @param interfaces   Interface names in order of their ids
+ * @param snaplen      Bytes captured per packet
+ * @param trace_start  Unix time the trace started at
+ */
+fn write_header<W: Write>(
+    writer: &mut W,
+    interfaces: &[String],
+    snaplen: u32,
+    trace_start: f64
+) -> std::io::Result<()> {
+    let mut shb = Vec::new();
+    shb.extend_from_slice(&BYTE_ORDER_MAGIC.to_ne_bytes());
+    shb.extend_from_slice(&1u16.to_ne_bytes()); // major version
+    shb.extend_from_slice(&0u16.to_ne_bytes()); // minor version
+    shb.extend_from_slice(&(-1i64).to_ne_bytes()); // section length unknown
+    push_option(&mut shb, SHB_USERAPPL,
+        format!("bridge-loss-emu {}", env!("CARGO_PKG_VERSION")).as_bytes());
+    push_option(&mut shb, OPT_COMMENT,
+        format!("{}{:.9}", TRACE_START_COMMENT, trace_start).as_bytes());
+    push_option(&mut shb, OPT_ENDOFOPT, &[]);
+    write_block(writer, BLOCK_SHB, &shb)?;
+
+    for name in interfaces {
+        let mut idb = Vec::new();
+        idb.extend_from_slice(&LINKTYPE_ETHERNET.to_ne_bytes());
+        idb.extend_from_slice(&0u16.to_ne_bytes()); // reserved
+        idb.extend_from_slice(&snaplen.to_ne_bytes());
+        push_option(&mut idb, IF_NAME, name.as_bytes());
+        push_option(&mut idb, IF_TSRESOL, &[9]);
+        push_option(&mut idb, OPT_ENDOFOPT, &[]);
+        write_block(writer, BLOCK_IDB, &idb)?;
+    }
+    Ok(())
+}
+
+/**
+ * Write a captured packet with optional comments
+ * @param writer     Output
+ * @param interface  Interface id
+ * @param timestamp  ns since the unix epoch
+ * @param original_len  Length of the packet on the wire
+ * @param data       Captured bytes
+ * @param comments   Comments attached to the packet
+ */
+fn write_packet<W: Write>(
+    writer: &mut W,
+    interface: u32,
+    timestamp: u64,
+    original_len: u32,
+    data: &[u8],
+    comments: &[String]
+) -> std::io::Result<()> {
+    let mut epb = Vec::with_capacity(data.len() + 32);
+    epb.extend_from_slice(&interface.to_ne_bytes());
+    epb.extend_from_slice(&((timestamp >> 32) as u32).to_ne_bytes());
+    epb.extend_from_slice(&(timestamp as u32).to_ne_bytes());
+    epb.extend_from_slice(&(data.len() as u32).to_ne_bytes());
+    epb.extend_from_slice(&original_len.to_ne_bytes());
+    epb.extend_from_slice(data);
+    epb.resize(epb.len().next_multiple_of(4), 0);
+    if !comments.is_empty() {
+        for comment in comments {
+            push_option(&mut epb, OPT_COMMENT, comment.as_bytes());
+        }
+        push_option(&mut epb, OPT_ENDOFOPT, &[]);
+    }
+    write_block(writer, BLOCK_EPB, &epb)
+}
+
+/**
+ * Write comments that never got a packet as interface statistics
+ * one block per comment with the time the comment was made
+ * @param writer    Output
+ * @param comments  Comments to write as (ns since the unix epoch, text)
+ */
+fn write_comments<W: Write>(writer: &mut W, comments: &[(u64, String)]) -> std::io::Result<()> {
+    for (timestamp, comment) in comments {
+        let mut isb = Vec::new();
+        isb.extend_from_slice(&0u32.to_ne_bytes()); // first interface
+        isb.extend_from_slice(&((timestamp >> 32) as u32).to_ne_bytes());
+        isb.extend_from_slice(&(*timestamp as u32).to_ne_bytes());
+        push_option(&mut isb, OPT_COMMENT, comment.as_bytes());
+        push_option(&mut isb, OPT_ENDOFOPT, &[]);
+        write_block(writer, BLOCK_ISB, &isb)?;
+    }
+    Ok(())
+}
+
+/**
+ * ns since the unix epoch
+ */
+fn now_ns() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64
+}
+
+/**
+ * Open an AF_PACKET socket bound to an interface
+ * has to be called in the namespace of the interface
+ * @param interface  Interface name
+ */
+fn open_socket(interface: &str) -> Result<OwnedFd, String> {
+    let name = std::ffi::CString::new(interface)
+        .map_err(|e| format!("Invalid interface name {}: {}", interface, e))?;
+    let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+    if index == 0 {
+        return Err(format!("Could not find interface {}", interface));
+    }
+
+    // no protocol yet so nothing is queued from other interfaces
+    // until the socket is bound to this one
+    let fd = unsafe { libc::socket(libc::AF_PACKET, libc::SOCK_RAW, 0) };
+    if fd < 0 {
+        return Err(format!("Could not open packet socket on {}: {}",
+                interface, std::io::Error::last_os_error()));
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    let mut addr: libc::sockaddr_ll = unsafe { std::mem::zeroed() };
+    addr.sll_family = libc::AF_PACKET as u16;
+    addr.sll_protocol = ETH_P_ALL.to_be();
+    addr.sll_ifindex = index as i32;
+    let ret = unsafe {
+        libc::bind(fd.as_raw_fd(), &addr as *const _ as *const libc::sockaddr,
+            std::mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t)
+    };
+    if ret != 0 {
+        return Err(format!("Could not bind packet socket to {}: {}",
+                interface, std::io::Error::last_os_error()));
+    }
+
+    // drop anything that got queued before the bind took effect
+    let mut buf = [0u8; 1];
+    while unsafe {
+        libc::recv(fd.as_raw_fd(), buf.as_mut_ptr() as *mut libc::c_void, buf.len(),
+            libc::MSG_DONTWAIT | libc::MSG_TRUNC)
+    } >= 0 {}
+
+    // kernel timestamps and a timeout so the thread can be stopped
+    let enable: libc::c_int = 1;
+    let timeout = libc::timeval {
+        tv_sec: 0,
+        tv_usec: POLL_INTERVAL.as_micros() as libc::suseconds_t,
+    };
+    let ret = unsafe {
+        libc::setsockopt(fd.as_raw_fd(), libc::SOL_SOCKET, libc::SO_TIMESTAMPNS,
+            &enable as *const _ as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t) |
+        libc::setsockopt(fd.as_raw_fd(), libc::SOL_SOCKET, libc::SO_RCVTIMEO,
+            &timeout as *const _ as *const libc::c_void,
+            std::mem::size_of::<libc::timeval>() as libc::socklen_t)
+    };
+    if ret != 0 {
+        return Err(format!("Could not set socket options on {}: {}",
+                interface, std::io::Error::last_os_error()));
+    }
+
+    Ok(fd)
+}
+
+/**
+ * Receive a single packet with its kernel timestamp
+ * @param fd   Packet socket
+ * @param buf  Buffer for the packet
+ * @return (original length, timestamp in ns) or None on timeout
+ */
+fn receive(fd: &OwnedFd, buf: &mut [u8]) -> Result<Option<(usize, u64)>, String> {
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    // room for a struct timespec control message
+    let mut control = [0u64; 8];
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = std::mem::size_of_val(&control) as _;
+
+    // MSG_TRUNC returns the real length of truncated packets
+    let len = unsafe { libc::recvmsg(fd.as_raw_fd(), &mut msg, libc::MSG_TRUNC) };
+    if len < 0 {
+        let error = std::io::Error::last_os_error();
+        return match error.kind() {
+            std::io::ErrorKind::WouldBlock | std::io::ErrorKind::Interrupted => Ok(None),
+            _ => Err(error.to_string()),
+        };
+    }
+
+    let mut timestamp = None;
+    let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+    while !cmsg.is_null() {
+        let header = unsafe { &*cmsg };
+        if header.cmsg_level == libc::SOL_SOCKET && header.cmsg_type == libc::SCM_TIMESTAMPNS {
+            let ts = unsafe {
+                std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::timespec)
+            };
+            timestamp = Some(ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64);
+        }
+        cmsg = unsafe { libc::CMSG_NXTHDR(&msg, cmsg) };
+    }
+
+    Ok(Some((len as usize, timestamp.unwrap_or_else(now_ns))))
+}
+
+/**
+ * Write records to the capture file until all senders are gone
+ * Comments are attached to the next captured packet
+ * @param writer     Output
+ * @param receiver   Records from capture threads and comments
+ */
+fn write_records(
+    mut writer: BufWriter<File>,
+    receiver: Receiver<Record>
+) -> std::io::Result<()> {
+    let mut comments: Vec<(u64, String)> = Vec::new();
+    loop {
+        // flush whenever there is nothing to do
+        // so little is lost if we get killed
+        let record = match receiver.try_recv() {
+            Ok(record) => record,
+            Err(_) => {
+                writer.flush()?;
+                match receiver.recv_timeout(POLL_INTERVAL) {
+                    Ok(record) => record,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            },
+        };
+
+        match record {
+            Record::Packet { interface, timestamp, original_len, data } => {
+                let texts: Vec<String> = comments.drain(..).map(|(_, text)| text).collect();
+                write_packet(&mut writer, interface, timestamp, original_len, &data, &texts)?;
+            },
+            Record::Comment { timestamp, text } => comments.push((timestamp, text)),
+        }
+    }
+
+    if !comments.is_empty() {
+        write_comments(&mut writer, &comments)?;
+    }
+    writer.flush()
+}
+
+/**
+ * Native packet capture writing pcapng
+ * Captures on several interfaces in (possibly) different namespaces
+ * each interface gets its own interface id in the file
+ */
+pub struct PacketCapture {
+    sender: Sender<Record>,
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+    writer: JoinHandle<std::io::Result<()>>,
+    file: String,
+}
+
+impl PacketCapture {
+    /**
+     * Start capturing
+     * @param config      Capture file and snaplen
+     * @param start       Start of the trace, stored in the file
+     *                    so packets can be matched with the trace
+     * @param interfaces  Namespaces and names of the interfaces to capture on
+     */
+    pub fn start(
+        config: &CaptureConfig,
+        start: Instant,
+        interfaces: &[(&NetNs, String)]
+    ) -> Result<Self, String> {
+        // sockets belong to the namespace they were created in
+        let mut sockets: Vec<(String, OwnedFd)> = Vec::new();
+        for (ns, interface) in interfaces {
+            let fd = ns.run(|_| open_socket(interface.as_str()))
+                .map_err(|e| format!("Failed to enter network namespace {}: {}",
+                        ns.path().display(), e))??;
+            sockets.push((interface.clone(), fd));
+        }
+
+        let file = File::create(&config.file)
+            .map_err(|e| format!("Could not create capture file {}: {}", config.file, e))?;
+        let mut writer = BufWriter::new(file);
+        let names: Vec<String> = sockets.iter().map(|(name, _)| name.clone()).collect();
+        let trace_start = unix_time() - start.elapsed().as_secs_f64();
+        write_header(&mut writer, &names, config.snaplen, trace_start)
+            .map_err(|e| format!("Could not write capture file {}: {}", config.file, e))?;
+
+        let (sender, receiver) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let writer = std::thread::Builder::new()
+            .name(String::from("capture-writer"))
+            .spawn(move || write_records(writer, receiver))
+            .map_err(|e| format!("Could not start capture writer: {}", e))?;
+
+        let mut threads = Vec::new();
+        for (id, (name, fd)) in sockets.into_iter().enumerate() {
+            let sender = sender.clone();
+            let stop = stop.clone();
+            let snaplen = config.snaplen as usize;
+            let thread = std::thread::Builder::new()
+                .name(format!("capture-{}", name))
+                .spawn(move || {
+                    let mut buf = vec![0u8; MAX_PACKET];
+                    while !stop.load(Ordering::Relaxed) {
+                        match receive(&fd, &mut buf) {
+                            Ok(Some((len, timestamp))) => {
+                                let captured = len.min(snaplen).min(buf.len());
+                                let record = Record::Packet {
+                                    interface: id as u32,
+                                    timestamp,
+                                    original_len: len as u32,
+                                    data: buf[..captured].to_vec(),
+                                };
+                                if sender.send(record).is_err() {
+                                    return;
+                                }
+                            },
+                            Ok(None) => continue,
+                            Err(e) => {
+                                eprintln!("[capture] Capturing on {} failed: {}", name, e);
+                                return;
+                            },
+                        }
+                    }
+                })
+                .map_err(|e| format!("Could not start capture thread: {}", e))?;
+            threads.push(thread);
+        }
+
+        println!("[capture] Capturing on {} to {} (snaplen {})",
+            names.join(", "), config.file, config.snaplen);
+
+        Ok(Self { sender, stop, threads, writer, file: config.file.clone() })
+    }
+
+    /**
+     * Add a comment to the capture
+     * it is attached to the next captured packet
+     * @param comment  Comment text
+     */
+    pub fn comment(&self, comment: String) {
+        let _ = self.sender.send(Record::Comment { timestamp: now_ns(), text: comment });
+    }
+
+    /**
+     * Stop capturing and finish the capture file
+     */
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for thread in self.threads {
+            let _ = thread.join();
+        }
+        drop(self.sender);
+
+        match self.writer.join() {
+            Ok(Ok(())) => println!("[capture] Wrote {}", self.file),
+            Ok(Err(e)) => eprintln!("[capture] Writing {} failed: {}", self.file, e),
+            Err(_) => eprintln!("[capture] Capture writer panicked"),
+        }
+    }
+}
+
+/**
+ * Interface of a capture file
+ */
+pub struct CaptureInterface {
+    /// if_name option, if there is one
+    pub name: Option<String>,
+    /// LINKTYPE_* of the packets
+    pub linktype: u16,
+    /// timestamp units per second
+    units: f64,
+}
+
+/**
+ * Packet read from a capture file
+ */
+pub struct CapturedPacket {
+    /// index into the interfaces of the capture
+    pub interface: usize,
+    /// seconds since the unix epoch
+    pub timestamp: f64,
+    /// captured bytes, may be shorter than the packet
+    pub data: Vec<u8>,
+}
+
+/**
+ * Reads packets from a pcapng file
+ * like the ones written by PacketCapture or tshark
+ */
+pub struct CaptureReader {
+    reader: std::io::BufReader<File>,
+    path: String,
+    /// byte order of the current section
+    big_endian: bool,
+    /// interfaces of the current section
+    pub interfaces: Vec<CaptureInterface>,
+    /// unix time the trace started at if the capture has it
+    pub trace_start: Option<f64>,
+}
+
+impl CaptureReader {
+    /**
+     * Open a pcapng file
+     * @param path  Path to the capture
+     */
+    pub fn open(path: &str) -> Result<Self, String> {
+        let file = File::open(path)
+            .map_err(|e| format!("Could not open capture file {}: {}", path, e))?;
+        let mut reader = Self {
+            reader: std::io::BufReader::new(file),
+            path: String::from(path),
+            big_endian: false,
+            interfaces: Vec::new(),
+            trace_start: None,
+        };
+
+        // has to start with a section header
+        match reader.read_block()? {
+            Some((BLOCK_SHB, body)) => reader.section(&body),
+            _ => return Err(format!("{} is not a pcapng file", path)),
+        }
+        Ok(reader)
+    }
+
+    fn u16_at(&self, buf: &[u8], offset: usize) -> u16 {
+        let bytes = [buf[offset], buf[offset + 1]];
+        if self.big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) }
+    }
+
+    fn u32_at(&self, buf: &[u8], offset: usize) -> u32 {
+        let bytes = [buf[offset], buf[offset + 1], buf[offset + 2], buf[offset + 3]];
+        if self.big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) }
+    }
+
+    /**
+     * Options of a block as (code, value)
+     * @param options  Option part of the block body
+     */
+    fn options<'a>(&self, options: &'a [u8]) -> Vec<(u16, &'a [u8])> {
+        let mut result = Vec::new();
+        let mut offset = 0;
+        while offset + 4 <= options.len() {
+            let code = self.u16_at(options, offset);
+            let len = self.u16_at(options, offset + 2) as usize;
+            if code == OPT_ENDOFOPT || offset + 4 + len > options.len() {
+                break;
+            }
+            result.push((code, &options[offset + 4..offset + 4 + len]));
+            offset += (4 + len).next_multiple_of(4);
+        }
+        result
+    }
+
+    /**
+     * Read the next block as (type, body)
+     * the byte order of a section header is detected from its magic
+     */
+    fn read_block(&mut self) -> Result<Option<(u32, Vec<u8>)>, String> {
+        use std::io::Read;
+
+        let mut header = [0u8; 8];
+        match self.reader.read_exact(&mut header) {
+            Ok(()) => (),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(format!("Could not read {}: {}", self.path, e)),
+        }
+
+        // section header type is a palindrome, the magic tells the byte order
+        if u32::from_le_bytes([header[0], header[1], header[2], header[3]]) == BLOCK_SHB {
+            let mut magic = [0u8; 4];
+            self.reader.read_exact(&mut magic)
+                .map_err(|e| format!("Could not read {}: {}", self.path, e))?;
+            self.big_endian = match magic {
+                m if u32::from_le_bytes(m) == BYTE_ORDER_MAGIC => false,
+                m if u32::from_be_bytes(m) == BYTE_ORDER_MAGIC => true,
+                _ => return Err(format!("Invalid section header in {}", self.path)),
+            };
+            let len = self.u32_at(&header, 4) as usize;
+            if len < 16 {
+                return Err(format!("Invalid section header in {}", self.path));
+            }
+            let mut body = magic.to_vec();
+            body.resize(len - 12, 0);
+            self.reader.read_exact(&mut body[4..])
+                .and_then(|_| self.reader.read_exact(&mut [0u8; 4]))
+                .map_err(|e| format!("Could not read {}: {}", self.path, e))?;
+            return Ok(Some((BLOCK_SHB, body)));
+        }
+
+        let block_type = self.u32_at(&header, 0);
+        let len = self.u32_at(&header, 4) as usize;
+        if len < 12 || !len.is_multiple_of(4) {
+            return Err(format!("Invalid block length {} in {}", len, self.path));
+        }
+        let mut body = vec![0u8; len - 8];
+        self.reader.read_exact(&mut body)
+            .map_err(|e| format!("Could not read {}: {}", self.path, e))?;
+        body.truncate(len - 12);
+        Ok(Some((block_type, body)))
+    }
+
+    /**
+     * Start a new section
+     * @param body  Body of the section header
+     */
+    fn section(&mut self, body: &[u8]) {
+        self.interfaces.clear();
+        for (code, value) in self.options(body.get(16..).unwrap_or_default()) {
+            let comment = String::from_utf8_lossy(value);
+            if code == OPT_COMMENT && let Some(time) = comment.strip_prefix(TRACE_START_COMMENT) {
+                self.trace_start = time.trim().parse().ok();
+            }
+        }
+    }
+
+    /**
+     * Read the next packet
+     * other blocks are handled or skipped on the way
+     */
+    pub fn next_packet(&mut self) -> Result<Option<CapturedPacket>, String> {
+        while let Some((block_type, body)) = self.read_block()? {
+            match block_type {
+                BLOCK_SHB => self.section(&body),
+                BLOCK_IDB if body.len() >= 8 => {
+                    let mut interface = CaptureInterface {
+                        name: None,
+                        linktype: self.u16_at(&body, 0),
+                        units: 1_000_000.0,
+                    };
+                    for (code, value) in self.options(&body[8..]) {
+                        match code {
+                            IF_NAME => interface.name = Some(
+                                String::from_utf8_lossy(value).trim_end_matches('\0').to_string()),
+                            IF_TSRESOL if !value.is_empty() => interface.units = match value[0] {
+                                v if v & 0x80 == 0 => 10f64.powi(v as i32),
+                                v => 2f64.powi((v & 0x7f) as i32),
+                            },
+                            _ => (),
+                        }
+                    }
+                    self.interfaces.push(interface);
+                },
+                BLOCK_EPB if body.len() >= 20 => {
+                    let interface = self.u32_at(&body, 0) as usize;
+                    let units = self.interfaces.get(interface)
+                        .ok_or(format!("Packet for unknown interface in {}", self.path))?
+                        .units;
+                    let timestamp = ((self.u32_at(&body, 4) as u64) << 32)
+                        | self.u32_at(&body, 8) as u64;
+                    let captured = self.u32_at(&body, 12) as usize;
+                    let data = body.get(20..20 + captured)
+                        .ok_or(format!("Truncated packet in {}", self.path))?
+                        .to_vec();
+                    return Ok(Some(CapturedPacket {
+                        interface,
+                        timestamp: timestamp as f64 / units,
+                        data,
+                    }));
+                },
+                _ => continue,
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pcapng_round_trip() {
+        let path = std::env::temp_dir()
+            .join(format!("bridge-loss-emu-{}.pcapng", std::process::id()));
+        let interfaces = [String::from("veth1"), String::from("veth2")];
+        let first = 1_700_000_000_123_456_789u64;
+        let second = first + 1_500_000;
+
+        let mut file = File::create(&path).unwrap();
+        write_header(&mut file, &interfaces, 128, 1_700_000_000.5).unwrap();
+        write_packet(&mut file, 1, first, 100, &[1, 2, 3, 4, 5],
+            &[String::from("outage 1")]).unwrap();
+        write_comments(&mut file, &[(first + 1000, String::from("no packet"))]).unwrap();
+        write_packet(&mut file, 0, second, 8, &[6, 7, 8, 9, 10, 11, 12, 13], &[]).unwrap();
+        drop(file);
+
+        let mut reader = CaptureReader::open(path.to_str().unwrap()).unwrap();
+        assert_eq!(reader.trace_start, Some(1_700_000_000.5));
+
+        // interfaces are read along with the first packet
+        let packet = reader.next_packet().unwrap().unwrap();
+        let names: Vec<_> = reader.interfaces.iter()
+            .map(|interface| interface.name.clone().unwrap())
+            .collect();
+        assert_eq!(names, interfaces);
+        assert!(reader.interfaces.iter().all(|interface| interface.linktype == LINKTYPE_ETHERNET));
+        assert_eq!(packet.interface, 1);
+        assert_eq!(packet.data, [1, 2, 3, 4, 5]);
+        assert!((packet.timestamp - first as f64 / 1e9).abs() < 1e-6);
+
+        // the comment block is skipped
+        let packet = reader.next_packet().unwrap().unwrap();
+        assert_eq!(packet.interface, 0);
+        assert_eq!(packet.data, [6, 7, 8, 9, 10, 11, 12, 13]);
+        assert!((packet.timestamp - second as f64 / 1e9).abs() < 1e-6);
+
+        assert!(reader.next_packet().unwrap().is_none());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
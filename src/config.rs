@@ -0,0 +1,344 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::capture::{CaptureConfig, DEFAULT_SNAPLEN};
+use crate::netem::LossModel;
+use crate::metrics::{unix_time, MetricsConfig};
+use crate::{Args, Command, NetemArgs, TestbedArgs, Test};
+
+/**
+ * Where the trace comes from
+ */
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TraceSource {
+    /// CSV trace file, see README for the format
+    pub file: String,
+    /// separate CSV trace for the downlink
+    pub downlink_file: Option<String>,
+    /// delay distribution file, defaults to /lib64/tc/pareto.dist
+    pub distribution: Option<String>,
+    /// loss outside of outages where the trace has none
+    pub base_loss: Option<LossModel>,
+}
+
+/**
+ * Packet capture settings
+ */
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Capture {
+    /// pcapng file, no capture if unset
+    pub file: Option<String>,
+    /// bytes captured per packet, defaults to 262144
+    pub snaplen: Option<u32>,
+}
+
+/**
+ * Where results are written to
+ */
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Output {
+    /// directory relative output paths are resolved against
+    /// created if it doesn't exist
+    pub directory: Option<String>,
+    /// throughput samples, CSV, JSON Lines (.jsonl) or JSON (.json)
+    pub throughput: Option<String>,
+    /// log of applied trace events, CSV, JSON Lines (.jsonl) or JSON (.json)
+    pub events: Option<String>,
+    /// every packet of the UDP probe, CSV, JSON Lines (.jsonl) or JSON (.json)
+    pub packets: Option<String>,
+    /// events of the stream player, CSV, JSON Lines (.jsonl) or JSON (.json)
+    pub player: Option<String>,
+    /// RTT samples pinging ns1 from ns2, CSV, JSON Lines (.jsonl) or JSON (.json)
+    pub ping: Option<String>,
+    /// time between two echo requests in ms
+    pub ping_interval: Option<u64>,
+    /// sample TCP_INFO of the test connections
+    /// written next to the throughput file as tcp_info_client/tcp_info_server
+    #[serde(default)]
+    pub tcp_info: bool,
+    /// run metadata as JSON
+    pub metadata: Option<String>,
+    /// sample interval for metrics in ms
+    pub interval: Option<u64>,
+}
+
+/**
+ * A full experiment as described by a config file
+ * or the command line
+ *
+ * Example:
+ *   [trace]
+ *   file = "traces/autobahn.csv"
+ *
+ *   [netem]
+ *   limit = 10000
+ *   seed = 42
+ *
+ *   [testbed]
+ *   subnet = "10.1.0.0/24"
+ *
+ *   [workload]
+ *   test = "download"
+ *
+ *   [capture]
+ *   file = "capture.pcapng"
+ *   snaplen = 128
+ *
+ *   [output]
+ *   directory = "results/autobahn"
+ *   throughput = "throughput.csv"
+ *   events = "events.csv"
+ *   ping = "ping.csv"
+ *   ping_interval = 50
+ *   tcp_info = true
+ *   metadata = "metadata.json"
+ *   interval = 100
+ */
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Experiment {
+    pub trace: TraceSource,
+    #[serde(default)]
+    pub netem: NetemArgs,
+    #[serde(default)]
+    pub testbed: TestbedArgs,
+    pub workload: Test,
+    #[serde(default)]
+    pub capture: Capture,
+    #[serde(default)]
+    pub output: Output,
+}
+
+/**
+ * Details of a run written next to the results
+ * The settings are taken before the run, the congestion control
+ * once the testbed is set up and it is known
+ */
+#[derive(Serialize)]
+pub struct RunMetadata {
+    /// metadata file
+    #[serde(skip)]
+    path: String,
+    /// seconds since the unix epoch
+    started: f64,
+    /// version of bridge-loss-emu
+    version: &'static str,
+    /// TCP congestion control used by server and client
+    congestion_control: String,
+    /// all settings of the run
+    experiment: serde_json::Value,
+}
+
+impl RunMetadata {
+    /**
+     * Write the metadata file
+     * @param congestion_control  TCP congestion control in effect for the run
+     */
+    pub fn write(mut self, congestion_control: &str) -> Result<(), String> {
+        self.congestion_control = String::from(congestion_control);
+        let json = serde_json::to_string_pretty(&self).map_err(|e| e.to_string())?;
+        std::fs::write(&self.path, json + "\n")
+            .map_err(|e| format!("Could not write metadata file {}: {}", self.path, e))
+    }
+}
+
+/**
+ * Run metadata as read back from the metadata file
+ */
+#[derive(Deserialize)]
+pub struct RecordedRun {
+    /// seconds since the unix epoch
+    pub started: f64,
+    pub version: String,
+    pub congestion_control: String,
+    /// settings with all paths as they were resolved for the run
+    pub experiment: Experiment,
+}
+
+impl RecordedRun {
+    /**
+     * Read the metadata file of a run
+     * @param path  Path to the JSON file
+     */
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Could not read metadata file {}: {}", path, e))?;
+        serde_json::from_str(content.as_str())
+            .map_err(|e| format!("Could not parse metadata file {}: {}", path, e))
+    }
+}
+
+/**
+ * Resolve path relative to base
+ * absolute paths are left as they are
+ * @param base  Directory relative paths start from
+ * @param path  Path to resolve
+ */
+fn resolve(base: &Path, path: &str) -> String {
+    base.join(path).to_string_lossy().into_owned()
+}
+
+impl Experiment {
+    /**
+     * Read an experiment from a TOML file
+     * Input paths (trace, distribution) are relative to the
+     * config file, capture and metrics files are relative to the output directory
+     * which itself is relative to the config file
+     * @param path  Path to the TOML file
+     */
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Could not read config file {}: {}", path, e))?;
+        let mut experiment: Self = toml::from_str(content.as_str())
+            .map_err(|e| format!("Could not parse config file {}: {}", path, e))?;
+
+        if experiment.output.interval == Some(0) {
+            return Err(format!("Metrics interval in {} must be at least 1ms", path));
+        }
+        if experiment.output.ping_interval == Some(0) {
+            return Err(format!("Ping interval in {} must be at least 1ms", path));
+        }
+        if experiment.capture.snaplen == Some(0) {
+            return Err(format!("Capture snaplen in {} must be at least 1", path));
+        }
+        if experiment.output.tcp_info && experiment.output.throughput.is_none() {
+            return Err(format!("tcp_info in {} requires a throughput file", path));
+        }
+
+        let base = Path::new(path).parent().unwrap_or(Path::new("")).to_path_buf();
+        experiment.trace.file = resolve(&base, &experiment.trace.file);
+        experiment.trace.downlink_file = experiment.trace.downlink_file
+            .map(|file| resolve(&base, &file));
+        experiment.trace.distribution = experiment.trace.distribution
+            .map(|file| resolve(&base, &file));
+
+        let output = base.join(experiment.output.directory.clone().unwrap_or_default());
+        experiment.output.directory = Some(output.to_string_lossy().into_owned());
+        experiment.capture.file = experiment.capture.file
+            .map(|file| resolve(&output, &file));
+        experiment.output.throughput = experiment.output.throughput
+            .map(|file| resolve(&output, &file));
+        experiment.output.events = experiment.output.events
+            .map(|file| resolve(&output, &file));
+        experiment.output.packets = experiment.output.packets
+            .map(|file| resolve(&output, &file));
+        experiment.output.player = experiment.output.player
+            .map(|file| resolve(&output, &file));
+        experiment.output.ping = experiment.output.ping
+            .map(|file| resolve(&output, &file));
+        experiment.output.metadata = experiment.output.metadata
+            .map(|file| resolve(&output, &file));
+
+        Ok(experiment)
+    }
+
+    /**
+     * Create the output directory if there is one
+     */
+    pub fn create_output_directory(&self) -> Result<(), String> {
+        if let Some(directory) = &self.output.directory {
+            let directory = PathBuf::from(directory);
+            if !directory.as_os_str().is_empty() {
+                std::fs::create_dir_all(&directory).map_err(|e| format!(
+                        "Could not create output directory {}: {}",
+                        directory.display(), e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     * Metadata of the run if there is a metadata file
+     * written with RunMetadata::write() once the testbed is set up
+     */
+    pub fn metadata(&self) -> Result<Option<RunMetadata>, String> {
+        let Some(path) = &self.output.metadata else {
+            return Ok(None);
+        };
+
+        Ok(Some(RunMetadata {
+            path: path.clone(),
+            started: unix_time(),
+            version: env!("CARGO_PKG_VERSION"),
+            congestion_control: String::new(),
+            experiment: serde_json::to_value(self).map_err(|e| e.to_string())?,
+        }))
+    }
+
+    /**
+     * Metrics settings for the workloads
+     */
+    pub fn metrics(&self) -> MetricsConfig {
+        let mut metrics = MetricsConfig {
+            throughput_file: self.output.throughput.clone(),
+            event_log_file: self.output.events.clone(),
+            packet_log_file: self.output.packets.clone(),
+            player_log_file: self.output.player.clone(),
+            ping_file: self.output.ping.clone(),
+            tcp_info: self.output.tcp_info,
+            ..MetricsConfig::default()
+        };
+        if let Some(interval) = self.output.interval {
+            metrics.interval = Duration::from_millis(interval);
+        }
+        if let Some(interval) = self.output.ping_interval {
+            metrics.ping_interval = Duration::from_millis(interval);
+        }
+        metrics
+    }
+
+    /**
+     * Packet capture settings, None without a capture file
+     */
+    pub fn capture(&self) -> Option<CaptureConfig> {
+        Some(CaptureConfig {
+            file: self.capture.file.clone()?,
+            snaplen: self.capture.snaplen.unwrap_or(DEFAULT_SNAPLEN),
+        })
+    }
+}
+
+impl TryFrom<Args> for Experiment {
+    type Error = String;
+
+    /**
+     * Build an experiment from command line arguments
+     * without a config file
+     */
+    fn try_from(args: Args) -> Result<Self, Self::Error> {
+        Ok(Self {
+            trace: TraceSource {
+                file: args.trace_file.ok_or(String::from("No trace file given"))?,
+                downlink_file: args.downlink_trace_file,
+                distribution: args.distribution_file,
+                base_loss: args.base_loss,
+            },
+            netem: args.netem,
+            testbed: args.testbed,
+            workload: match args.command {
+                Some(Command::Test(test)) => test,
+                _ => return Err(String::from("No test given")),
+            },
+            capture: Capture {
+                file: args.capture_file,
+                snaplen: args.snaplen,
+            },
+            output: Output {
+                directory: None,
+                throughput: args.throughput_file,
+                events: args.event_log_file,
+                packets: args.packet_log_file,
+                player: args.player_log_file,
+                ping: args.ping_file,
+                ping_interval: args.ping_interval,
+                tcp_info: args.tcp_info,
+                metadata: args.metadata_file,
+                interval: Some(args.interval),
+            },
+        })
+    }
+}
@@ -1,15 +1,36 @@
 use std::process::exit;
 use csv::Reader;
-use clap::{Parser, Subcommand};
+use clap::{Args as _, CommandFactory, FromArgMatches, Parser, Subcommand};
+use config::Experiment;
+use matrix::Axis;
+use serde::{Deserialize, Serialize};
+use netem::{LossModel, NetemConfig, NetemSlot};
+use scenario::RunSettings;
+use testbed::TestbedConfig;
+use trace::Trace;
 use users::get_effective_uid;
 
 // modules
+mod analyze;
+mod capture;
+mod config;
+mod matrix;
+mod metrics;
+mod tcp_info;
+mod test_bidirectional;
 mod test_download;
 mod test_upload;
 mod test_stream;
 mod test_host;
+mod test_udp;
 mod testbed;
+mod netem;
+mod ping;
+mod player;
+mod report;
+mod scenario;
 mod trace;
+mod udp_probe;
 mod webserver;
 mod webclient;
 mod rtnetlink_utils;
@@ -18,46 +39,426 @@ mod rtnetlink_utils;
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
+    /// TOML file describing the whole experiment
+    /// replaces all other options and the test subcommand
+    #[arg(long, conflicts_with_all = [
+        "file", "downlink-file", "distribution", "base-loss", "pcap", "snaplen",
+        "throughput", "event-log", "packet-log", "player-log", "ping",
+        "ping-interval", "tcp-info", "metadata", "interval",
+        "NetemArgs", "TestbedArgs"
+    ])]
+    config: Option<String>,
+
     /// CSV file with loss trace of form timestamp,lossTime
+    /// optionally with loss,latency,jitter,rate columns
+    /// and their per direction variants e.g. rateUp,rateDown
     #[arg(id = "file", short, long)]
-    trace_file: String,
+    trace_file: Option<String>,
+
+    /// Separate CSV file for the downlink
+    /// the trace file is then only used for the uplink
+    #[arg(id = "downlink-file", long)]
+    downlink_trace_file: Option<String>,
 
     /// Path to a delay distribution file
     /// Defaults to /lib64/tc/pareto.dist
     #[arg(id = "distribution", short, long)]
     distribution_file: Option<String>,
 
-    /// optional tshark packet capture
+    /// Loss outside of outages for traces without a loss column
+    /// in % or as tc-netem(8) style loss model, defaults to 0
+    #[arg(id = "base-loss", long)]
+    base_loss: Option<LossModel>,
+
+    /// Capture packets on both ends of the veth pair to a pcapng file
+    /// trace events are added as packet comments
     #[arg(id = "pcap", short, long)]
     capture_file: Option<String>,
 
+    /// Bytes captured per packet, defaults to 262144
+    #[arg(id = "snaplen", long, requires = "pcap",
+        value_parser = clap::value_parser!(u32).range(1..))]
+    snaplen: Option<u32>,
+
+    /// Record throughput samples of the download/upload
+    /// written as JSON Lines for .jsonl, a JSON array for .json files, CSV otherwise
+    #[arg(id = "throughput", long)]
+    throughput_file: Option<String>,
+
+    /// Log every applied trace event with its actual timing
+    /// written as JSON Lines for .jsonl, a JSON array for .json files, CSV otherwise
+    #[arg(id = "event-log", long)]
+    event_log_file: Option<String>,
+
+    /// Log every packet of the udp test with its one-way delay
+    /// written as JSON Lines for .jsonl, a JSON array for .json files, CSV otherwise
+    #[arg(id = "packet-log", long)]
+    packet_log_file: Option<String>,
+
+    /// Log the segments, bitrate switches and stalls of the stream test
+    /// written as JSON Lines for .jsonl, a JSON array for .json files, CSV otherwise
+    #[arg(id = "player-log", long)]
+    player_log_file: Option<String>,
+
+    /// Ping ns1 from ns2 next to the workload and log the RTTs
+    /// written as JSON Lines for .jsonl, a JSON array for .json files, CSV otherwise
+    #[arg(id = "ping", long)]
+    ping_file: Option<String>,
+
+    /// Interval between two pings in ms, defaults to 100
+    #[arg(id = "ping-interval", long, requires = "ping",
+        value_parser = clap::value_parser!(u64).range(1..))]
+    ping_interval: Option<u64>,
+
+    /// Sample TCP_INFO on client and server during download/upload
+    /// written next to the throughput file
+    #[arg(id = "tcp-info", long, requires = "throughput")]
+    tcp_info: bool,
+
+    /// Write the experiment settings and run details as JSON
+    #[arg(id = "metadata", long)]
+    metadata_file: Option<String>,
+
+    /// Interval between metrics samples in ms
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u64).range(1..))]
+    interval: u64,
+
+    /// netem settings that stay the same over the whole trace
+    #[command(flatten)]
+    netem: NetemArgs,
+
+    /// testbed topology
+    #[command(flatten)]
+    testbed: TestbedArgs,
+
     /// Test to run
     #[command(subcommand)]
-    test: Test,
+    command: Option<Command>,
+}
+
+/// netem settings that stay the same over the whole trace
+/// see tc-netem(8) for details
+#[derive(clap::Args, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+struct NetemArgs {
+    /// Limit for packets in queue
+    /// taken from
+    /// https://github.com/sys-uos/Starlink-on-the-Autobahn/blob/main/loss_emulation.py
+    #[arg(long, default_value_t = 10_000)]
+    limit: u32,
+
+    /// Loss correlation in % (random loss only)
+    #[arg(long, default_value_t = 0.0)]
+    loss_correlation: f32,
+
+    /// Latency correlation in %
+    #[arg(long, default_value_t = 0.0)]
+    latency_correlation: f32,
+
+    /// Probability to reorder packets in %
+    #[arg(long, default_value_t = 0.0)]
+    reorder: f32,
+
+    /// Reorder correlation in %
+    #[arg(long, default_value_t = 0.0)]
+    reorder_correlation: f32,
+
+    /// Only reorder every gap-th packet
+    #[arg(long, default_value_t = 0)]
+    gap: u32,
+
+    /// Probability to corrupt packets in %
+    #[arg(long, default_value_t = 0.0)]
+    corrupt: f32,
+
+    /// Corrupt correlation in %
+    #[arg(long, default_value_t = 0.0)]
+    corrupt_correlation: f32,
+
+    /// Probability to duplicate packets in %
+    #[arg(long, default_value_t = 0.0)]
+    duplicate: f32,
+
+    /// Duplicate correlation in %
+    #[arg(long, default_value_t = 0.0)]
+    duplicate_correlation: f32,
+
+    /// Mark packets with ECN instead of dropping them
+    #[arg(long)]
+    ecn: bool,
+
+    /// Slot based transmission of form "MIN_DELAY [MAX_DELAY [PACKETS [BYTES]]]"
+    /// delays are in ms
+    #[arg(long)]
+    slot: Option<NetemSlot>,
+
+    /// Seed for the netem PRNG to make loss reproducible
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Per packet overhead for the rate limit in bytes
+    #[arg(long, default_value_t = 0, allow_hyphen_values = true)]
+    packet_overhead: i32,
+
+    /// Link layer cell size for the rate limit in bytes
+    #[arg(long, default_value_t = 0)]
+    cell_size: u32,
+
+    /// Per cell overhead for the rate limit in bytes
+    #[arg(long, default_value_t = 0, allow_hyphen_values = true)]
+    cell_overhead: i32,
+}
+
+impl Default for NetemArgs {
+    /// same defaults as on the command line
+    fn default() -> Self {
+        let matches = NetemArgs::augment_args(clap::Command::new("netem"))
+            .get_matches_from(["netem"]);
+        NetemArgs::from_arg_matches(&matches).unwrap()
+    }
+}
+
+impl From<NetemArgs> for NetemConfig {
+    fn from(args: NetemArgs) -> Self {
+        let mut config = NetemConfig::new()
+            .limit(args.limit)
+            .loss_correlation(args.loss_correlation)
+            .latency_correlation(args.latency_correlation)
+            .reorder(args.reorder, args.reorder_correlation)
+            .gap(args.gap)
+            .corrupt(args.corrupt, args.corrupt_correlation)
+            .duplicate(args.duplicate, args.duplicate_correlation)
+            .ecn(args.ecn)
+            .rate_overhead(args.packet_overhead, args.cell_size, args.cell_overhead);
+        if let Some(slot) = args.slot {
+            config = config.slot(slot);
+        }
+        if let Some(seed) = args.seed {
+            config = config.seed(seed);
+        }
+        config
+    }
+}
+
+/// testbed topology
+/// not used in host mode
+#[derive(clap::Args, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+struct TestbedArgs {
+    /// Name prefix for namespaces and interfaces
+    /// defaults to the first free one of ble0, ble1, ...
+    #[arg(long)]
+    prefix: Option<String>,
+
+    /// Subnet for the testbed, ns1 gets the 1st and ns2 the 2nd host
+    #[arg(long, default_value = "10.0.0.0/24")]
+    subnet: String,
+
+    /// IPv6 subnet for the testbed, assigned like --subnet
+    #[arg(long, default_value = "fd00::/64")]
+    subnet6: String,
+
+    /// Run the workloads over IPv6 instead of IPv4
+    #[arg(long)]
+    ipv6: bool,
+
+    /// Name of the interface in ns1 (prefixed)
+    #[arg(long, default_value = "veth1")]
+    if1: String,
+
+    /// Name of the interface in ns2 (prefixed)
+    #[arg(long, default_value = "veth2")]
+    if2: String,
+
+    /// Name of the ifb interface in ns2 (prefixed)
+    #[arg(long, default_value = "ifb2")]
+    ifb2: String,
+
+    /// TCP congestion control for server and client e.g. cubic, bbr, reno
+    /// defaults to the one of the host
+    #[arg(long)]
+    congestion_control: Option<String>,
+}
+
+impl Default for TestbedArgs {
+    /// same defaults as on the command line
+    fn default() -> Self {
+        let matches = TestbedArgs::augment_args(clap::Command::new("testbed"))
+            .get_matches_from(["testbed"]);
+        TestbedArgs::from_arg_matches(&matches).unwrap()
+    }
+}
+
+impl From<TestbedArgs> for TestbedConfig {
+    fn from(args: TestbedArgs) -> Self {
+        Self {
+            prefix: args.prefix,
+            subnet: args.subnet,
+            subnet6: args.subnet6,
+            ipv6: args.ipv6,
+            if1: args.if1,
+            if2: args.if2,
+            ifb2: args.ifb2,
+            congestion_control: args.congestion_control,
+        }
+    }
+}
+
+/// Subcommands
+#[derive(Subcommand, Debug)]
+enum Command {
+    #[command(flatten)]
+    Test(Test),
+    /// Run every combination of parameter axes
+    /// each in its own process with a fresh testbed
+    Matrix(MatrixArgs),
+    /// Analyze how TCP recovered from every outage of a finished run
+    /// from its packet capture
+    Analyze(AnalyzeArgs),
+    /// Write an HTML or Markdown report of a finished run
+    /// with plots of throughput and RTT
+    Report(ReportArgs),
+}
+
+/// Batch of experiments
+#[derive(clap::Args, Debug)]
+struct MatrixArgs {
+    /// TOML file with the experiment all runs are based on
+    #[arg(long)]
+    base: String,
+
+    /// Directory for the results, one subdirectory per run
+    #[arg(long, default_value = "results")]
+    results: String,
+
+    /// How often every combination is run
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    repetitions: u32,
+
+    /// Parameter axis of form KEY=VALUE,VALUE,...
+    /// where KEY is a dotted key of the config file
+    /// e.g. workload.test=download,upload or testbed.congestion_control=cubic,bbr
+    #[arg(long = "axis")]
+    axes: Vec<Axis>,
+}
+
+/// Analysis of a finished run
+#[derive(clap::Args, Debug)]
+struct AnalyzeArgs {
+    /// pcapng capture of the run
+    #[arg(long)]
+    pcap: String,
+
+    /// Trace the run was played with
+    #[arg(long)]
+    trace: String,
+
+    /// Separate downlink trace the run was played with
+    #[arg(long)]
+    downlink_trace: Option<String>,
+
+    /// Interface of the capture to analyze
+    /// defaults to the first one (veth1 in captures of this program)
+    #[arg(long)]
+    interface: Option<String>,
+
+    /// Server port of the analyzed connections
+    #[arg(long, default_value_t = webserver::PORT)]
+    port: u16,
+
+    /// Unix time the trace started at
+    /// taken from the capture by default
+    #[arg(long)]
+    trace_start: Option<f64>,
+
+    /// Bin size for the throughput after an outage in ms
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u64).range(1..))]
+    interval: u64,
+
+    /// Time before an outage its throughput is averaged over in ms
+    #[arg(long, default_value_t = 2000, value_parser = clap::value_parser!(u64).range(1..))]
+    baseline: u64,
+
+    /// Share of the pre-outage throughput in % that counts as recovered
+    #[arg(long, default_value_t = 90.0)]
+    threshold: f64,
+
+    /// Write the results per outage to a file
+    /// written as JSON Lines for .jsonl, a JSON array for .json files, CSV otherwise
+    #[arg(short, long)]
+    output: Option<String>,
+}
+
+/// Report of a finished run
+#[derive(clap::Args, Debug)]
+struct ReportArgs {
+    /// Metadata file of the run (--metadata)
+    /// the trace, metrics and capture are found through it
+    #[arg(long)]
+    metadata: String,
+
+    /// Report file, Markdown for .md files, HTML otherwise
+    #[arg(short, long)]
+    output: String,
+
+    /// Server port of the connections in the capture
+    #[arg(long, default_value_t = webserver::PORT)]
+    port: u16,
 }
 
 /// Test to run
+/// in a config file this is the [workload] table
+/// with the test name in the "test" key
 #[derive(Subcommand)]
-#[derive(Debug)]
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "test", rename_all = "lowercase", deny_unknown_fields)]
 #[command()]
 enum Test {
     /// Download Test
-    Download,
+    Download {
+        /// Number of parallel connections
+        /// defaults to 1
+        #[arg(long)]
+        flows: Option<usize>,
+    },
     /// Upload Test
-    Upload,
+    Upload {
+        /// Number of parallel connections
+        /// defaults to 1
+        #[arg(long)]
+        flows: Option<usize>,
+    },
+    /// Download and upload at the same time
+    /// over separate connections
+    Bidirectional,
     /// Stream Test
+    /// adaptive streaming of a synthetic video in 2s segments
     Stream {
-        /// Video file used for the stream test
-        #[arg(id = "video", short, long)]
-        video_file: String,
-
-        /// ffmpeg style video bitrate
-        /// defaults to 5000k
-        #[arg(id = "bitrate", short, long)]
-        video_bitrate: Option<String>,
+        /// Highest bitrate level in kbit/s the player may pick
+        /// defaults to all levels (500 to 16000)
+        #[arg(long)]
+        max_bitrate: Option<u64>,
+
+        /// Playout buffer the player fills up to in s
+        /// defaults to 30
+        #[arg(long)]
+        buffer: Option<f64>,
+    },
+    /// UDP packets at a constant rate from ns1 to ns2
+    /// with per packet loss, reordering and one-way delay
+    Udp {
+        /// Rate in mbit/s
+        /// defaults to 10
+        #[arg(long)]
+        rate: Option<f64>,
+
+        /// UDP payload size in bytes
+        /// defaults to 1200
+        #[arg(long)]
+        size: Option<usize>,
     },
     /// Play trace on a host interface
-    /// WARNING: this will replace your current qdisc
+    /// the current qdisc is restored afterwards
     Host {
         /// Host interface to be used for trace playback
         #[arg(short, long)]
@@ -69,6 +470,54 @@ fn main() {
     // setup and checks
     let args = Args::parse();
 
+    // try to read file
+    let open = |path: &str| Reader::from_path(path).unwrap_or_else(|_| {
+        eprintln!("Could not open csv file {} for reading", path);
+        exit(1);
+    });
+
+    // analysis and reports work on files only and need no privileges
+    if let Some(Command::Report(report)) = args.command {
+        if args.config.is_some() || args.trace_file.is_some() {
+            Args::command().error(clap::error::ErrorKind::ArgumentConflict,
+                "report takes the run from --metadata").exit();
+        }
+        if let Err(e) = report::report(report.metadata.as_str(), report.output.as_str(),
+            report.port) {
+            eprintln!("[report] {}", e);
+            exit(1);
+        }
+        exit(0);
+    }
+    if let Some(Command::Analyze(analyze)) = args.command {
+        if args.config.is_some() || args.trace_file.is_some() {
+            Args::command().error(clap::error::ErrorKind::ArgumentConflict,
+                "analyze takes the trace from --trace").exit();
+        }
+        let mut rdr = open(analyze.trace.as_str());
+        let trace = match &analyze.downlink_trace {
+            Some(downlink_trace) => Trace::new_asymmetric(&mut rdr, &mut open(downlink_trace), None),
+            None => Trace::new(&mut rdr, None),
+        };
+        let trace = trace.unwrap_or_else(|e| {
+            eprintln!("Invalid trace: {}", e);
+            exit(1);
+        });
+        let config = analyze::AnalyzeConfig {
+            port: analyze.port,
+            interface: analyze.interface,
+            trace_start: analyze.trace_start,
+            interval: analyze.interval as f64 / 1000.0,
+            baseline: analyze.baseline as f64 / 1000.0,
+            threshold: analyze.threshold / 100.0,
+        };
+        if let Err(e) = analyze::analyze(analyze.pcap.as_str(), &trace, &config, analyze.output) {
+            eprintln!("[analyze] {}", e);
+            exit(1);
+        }
+        exit(0);
+    }
+
     // we need to be root in order to create network namespaces or interfaces
     if get_effective_uid() != 0 {
         eprintln!("Elevated privileges are required \
@@ -76,28 +525,113 @@ fn main() {
         exit(1);
     }
 
-    // try to read file
-    let mut rdr = Reader::from_path(args.trace_file.as_str()).unwrap_or_else(|_| {
-        eprintln!("Could not open csv file {} for reading", args.trace_file.as_str());
+    // batch runs start this program once per run
+    if let Some(Command::Matrix(matrix)) = args.command {
+        if args.config.is_some() || args.trace_file.is_some() {
+            Args::command().error(clap::error::ErrorKind::ArgumentConflict,
+                "matrix takes the experiment from --base").exit();
+        }
+        if let Err(e) = matrix::run_matrix(
+            matrix.base, matrix.results, matrix.repetitions, matrix.axes) {
+            eprintln!("[matrix] {}", e);
+            exit(1);
+        }
+        exit(0);
+    }
+
+    // experiment from either config file or command line
+    let experiment = match &args.config {
+        Some(config) => {
+            if args.command.is_some() {
+                Args::command().error(clap::error::ErrorKind::ArgumentConflict,
+                    "the test is taken from the config file").exit();
+            }
+            Experiment::from_file(config.as_str())
+        },
+        None => {
+            if args.trace_file.is_none() {
+                Args::command().error(clap::error::ErrorKind::MissingRequiredArgument,
+                    "--file is required without --config").exit();
+            }
+            if args.command.is_none() {
+                Args::command().error(clap::error::ErrorKind::MissingSubcommand,
+                    "a test is required").exit();
+            }
+            Experiment::try_from(args)
+        },
+    };
+    let experiment = experiment.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        exit(1);
+    });
+    let metadata = experiment.create_output_directory()
+        .and_then(|_| experiment.metadata())
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            exit(1);
+        });
+
+    let mut rdr = open(experiment.trace.file.as_str());
+    let trace = match &experiment.trace.downlink_file {
+        Some(downlink_file) => Trace::new_asymmetric(&mut rdr, &mut open(downlink_file),
+            experiment.trace.base_loss.clone()),
+        None => Trace::new(&mut rdr, experiment.trace.base_loss.clone()),
+    };
+    let mut trace = trace.unwrap_or_else(|e| {
+        eprintln!("Invalid trace: {}", e);
         exit(1);
     });
 
+    // before the netem and testbed settings are moved out of the experiment
+    let metrics = experiment.metrics();
+    let capture = experiment.capture();
+
+    // base netem config
+    let netem = NetemConfig::from(experiment.netem);
+    if let Err(e) = netem.validate() {
+        eprintln!("Invalid netem settings: {}", e);
+        exit(1);
+    }
+
+    // testbed topology
+    let testbed = TestbedConfig::from(experiment.testbed);
+
+    let settings = RunSettings {
+        distribution_file: experiment.trace.distribution,
+        netem,
+        testbed,
+        capture,
+        metrics,
+        metadata,
+    };
+
     // setup test
-    match args.test {
-        Test::Download => test_download::run_test(
-            &mut rdr, args.distribution_file.clone(), args.capture_file.clone()),
-        Test::Upload => test_upload::run_test(
-            &mut rdr, args.distribution_file.clone(), args.capture_file.clone()),
+    match experiment.workload {
+        Test::Download { flows } => scenario::run(
+            test_download::Download { flows: flows.unwrap_or(1) }, &mut trace, settings),
+        Test::Upload { flows } => scenario::run(
+            test_upload::Upload { flows: flows.unwrap_or(1) }, &mut trace, settings),
+        Test::Bidirectional => scenario::run(test_bidirectional::Bidirectional,
+            &mut trace, settings),
         Test::Stream {
-            video_file: vfile,
-            video_bitrate: vrate
-        } => test_stream::run_test(
-            &mut rdr, args.distribution_file.clone(), args.capture_file.clone(),
-            vfile.clone(), vrate.clone()),
+            max_bitrate,
+            buffer
+        } => scenario::run(test_stream::Stream {
+                max_bitrate,
+                buffer: buffer.unwrap_or(player::DEFAULT_BUFFER),
+            },
+            &mut trace, settings),
+        Test::Udp {
+            rate,
+            size
+        } => scenario::run(test_udp::Udp {
+                rate: rate.unwrap_or(udp_probe::DEFAULT_RATE),
+                size: size.unwrap_or(udp_probe::DEFAULT_SIZE),
+            },
+            &mut trace, settings),
         Test::Host {
             interface: iface
-        } => test_host::run_test(
-            &mut rdr, args.distribution_file.clone(), iface.clone()),
+        } => test_host::run_test(&mut trace, iface, settings),
     }
 
     exit(0);
@@ -0,0 +1,400 @@
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::time::Instant;
+
+use crate::config::Experiment;
+use crate::metrics::unix_time;
+
+/**
+ * Input paths of an experiment
+ * relative ones are resolved against the base config
+ */
+const INPUT_KEYS: [&str; 3] = [
+    "trace.file",
+    "trace.downlink_file",
+    "trace.distribution",
+];
+
+/**
+ * Outputs every run records unless the base config sets them
+ */
+const DEFAULT_OUTPUTS: [(&str, &str); 3] = [
+    ("output.metadata", "metadata.json"),
+    ("output.throughput", "throughput.csv"),
+    ("output.events", "events.csv"),
+];
+
+/**
+ * A parameter axis of the matrix
+ * e.g. testbed.congestion_control=cubic,bbr
+ */
+#[derive(Clone, Debug)]
+pub struct Axis {
+    /// dotted key into the config file
+    key: String,
+    /// values the key takes
+    values: Vec<toml::Value>,
+}
+
+/**
+ * Parse a single axis value as TOML
+ * anything that isn't valid TOML is taken as string
+ * @param value  Value as given on the command line
+ */
+fn parse_value(value: &str) -> toml::Value {
+    toml::from_str::<toml::Table>(format!("value = {}", value).as_str())
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or(toml::Value::String(String::from(value)))
+}
+
+/**
+ * Format a value for the manifest
+ * strings without the quotes
+ * @param value  TOML value
+ */
+fn format_value(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+impl FromStr for Axis {
+    type Err = String;
+
+    /**
+     * Parse an axis from "KEY=VALUE,VALUE,..."
+     */
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, values) = s.split_once('=')
+            .ok_or(format!("Axis {} is missing a '='", s))?;
+        let key = String::from(key.trim());
+        if key.is_empty() || key.split('.').any(|part| part.is_empty()) {
+            return Err(format!("Invalid key in axis {}", s));
+        }
+
+        let values: Vec<toml::Value> = values.split(',')
+            .map(|value| value.trim())
+            .filter(|value| !value.is_empty())
+            .map(parse_value)
+            .collect();
+        if values.is_empty() {
+            return Err(format!("Axis {} has no values", s));
+        }
+
+        Ok(Self { key, values })
+    }
+}
+
+/**
+ * Look up a dotted key in a TOML table
+ * @param table  Table to search
+ * @param key    Dotted key e.g. trace.file
+ */
+fn get_key<'a>(table: &'a toml::Table, key: &str) -> Option<&'a toml::Value> {
+    let (path, last) = match key.rsplit_once('.') {
+        Some((path, last)) => (Some(path), last),
+        None => (None, key),
+    };
+    let mut table = table;
+    for part in path.into_iter().flat_map(|path| path.split('.')) {
+        table = table.get(part)?.as_table()?;
+    }
+    table.get(last)
+}
+
+/**
+ * Set a dotted key in a TOML table
+ * missing tables on the way are created
+ * @param table  Table to modify
+ * @param key    Dotted key e.g. testbed.congestion_control
+ * @param value  New value
+ */
+fn set_key(table: &mut toml::Table, key: &str, value: toml::Value) -> Result<(), String> {
+    let (path, last) = match key.rsplit_once('.') {
+        Some((path, last)) => (Some(path), last),
+        None => (None, key),
+    };
+    let mut table = table;
+    for part in path.into_iter().flat_map(|path| path.split('.')) {
+        table = table.entry(part)
+            .or_insert(toml::Value::Table(toml::Table::new()))
+            .as_table_mut()
+            .ok_or(format!("{} in {} is not a table", part, key))?;
+    }
+    table.insert(String::from(last), value);
+    Ok(())
+}
+
+/**
+ * Every combination of the axes values
+ * the last axis changes fastest
+ * @param axes  Parameter axes
+ */
+fn combinations(axes: &[Axis]) -> Vec<Vec<toml::Value>> {
+    axes.iter().fold(vec![Vec::new()], |combinations, axis| {
+        combinations.iter()
+            .flat_map(|combination| axis.values.iter().map(move |value| {
+                let mut combination = combination.clone();
+                combination.push(value.clone());
+                combination
+            }))
+            .collect()
+    })
+}
+
+/**
+ * Build the config of a single run
+ * @param base        Base config
+ * @param base_dir    Directory of the base config
+ * @param axes        Parameter axes
+ * @param combination One value per axis
+ */
+fn run_config(
+    base: &toml::Table,
+    base_dir: &Path,
+    axes: &[Axis],
+    combination: &[toml::Value]
+) -> Result<toml::Table, String> {
+    let mut config = base.clone();
+    for (axis, value) in axes.iter().zip(combination) {
+        set_key(&mut config, axis.key.as_str(), value.clone())?;
+    }
+
+    // inputs stay where they are, the run config lives somewhere else
+    for key in INPUT_KEYS {
+        if let Some(toml::Value::String(path)) = get_key(&config, key) {
+            let path = std::path::absolute(base_dir.join(path))
+                .map_err(|e| format!("Could not resolve {} {}: {}", key, path, e))?;
+            set_key(&mut config, key, toml::Value::String(
+                    path.to_string_lossy().into_owned()))?;
+        }
+    }
+
+    // outputs go next to the run config
+    if let Some(output) = config.get_mut("output").and_then(|output| output.as_table_mut()) {
+        output.remove("directory");
+    }
+    for (key, file) in DEFAULT_OUTPUTS {
+        if get_key(&config, key).is_none() {
+            set_key(&mut config, key, toml::Value::String(String::from(file)))?;
+        }
+    }
+
+    Ok(config)
+}
+
+/**
+ * Run every combination of axes repetitions times
+ * Each run gets its own directory <results>/run-NNNN with the
+ * experiment.toml it was started with, its outputs and a log.
+ * <results>/manifest.csv lists all runs with their parameters.
+ * @param base         Path to the base config file
+ * @param results      Results directory
+ * @param repetitions  How often every combination is run
+ * @param axes         Parameter axes
+ */
+pub fn run_matrix(
+    base: String,
+    results: String,
+    repetitions: u32,
+    axes: Vec<Axis>
+) -> Result<(), String> {
+    let base_dir = Path::new(&base).parent().unwrap_or(Path::new("")).to_path_buf();
+    let content = fs::read_to_string(&base)
+        .map_err(|e| format!("Could not read base config {}: {}", base, e))?;
+    let base_config: toml::Table = toml::from_str(content.as_str())
+        .map_err(|e| format!("Could not parse base config {}: {}", base, e))?;
+
+    // results directory with a copy of the base config
+    let results = PathBuf::from(results);
+    fs::create_dir_all(&results)
+        .map_err(|e| format!("Could not create results directory {}: {}",
+                results.display(), e))?;
+    fs::write(results.join("base.toml"), &content)
+        .map_err(|e| format!("Could not copy base config: {}", e))?;
+
+    // check all runs before starting the first one
+    let combinations = combinations(&axes);
+    let mut configs: Vec<(Vec<toml::Value>, toml::Table)> = Vec::new();
+    for combination in combinations {
+        let config = run_config(&base_config, &base_dir, &axes, &combination)?;
+        let _: Experiment = config.clone().try_into()
+            .map_err(|e| format!("Invalid config for {}: {}",
+                    axes.iter().zip(&combination)
+                        .map(|(axis, value)| format!("{}={}", axis.key, format_value(value)))
+                        .collect::<Vec<String>>()
+                        .join(" "),
+                    e))?;
+        configs.push((combination, config));
+    }
+
+    let total = configs.len() * repetitions as usize;
+    println!("[matrix] {} combinations x {} repetitions = {} runs",
+        configs.len(), repetitions, total);
+
+    let exe = std::env::current_exe()
+        .map_err(|e| format!("Could not find own executable: {}", e))?;
+
+    // manifest lists every run as soon as it is done
+    let manifest_path = results.join("manifest.csv");
+    let mut manifest = csv::Writer::from_path(&manifest_path)
+        .map_err(|e| format!("Could not create {}: {}", manifest_path.display(), e))?;
+    let mut header = vec![String::from("run"), String::from("directory"),
+        String::from("repetition")];
+    header.extend(axes.iter().map(|axis| axis.key.clone()));
+    header.extend([String::from("started"), String::from("duration"),
+        String::from("exit_code")]);
+    manifest.write_record(&header).map_err(|e| e.to_string())?;
+
+    let mut run = 0;
+    let mut failed = 0;
+    for repetition in 1..=repetitions {
+        for (combination, config) in configs.iter() {
+            run += 1;
+            let name = format!("run-{:04}", run);
+            let directory = results.join(&name);
+            fs::create_dir_all(&directory)
+                .map_err(|e| format!("Could not create {}: {}", directory.display(), e))?;
+
+            let config_path = directory.join("experiment.toml");
+            let config_toml = toml::to_string_pretty(config).map_err(|e| e.to_string())?;
+            fs::write(&config_path, config_toml)
+                .map_err(|e| format!("Could not write {}: {}", config_path.display(), e))?;
+
+            let parameters = axes.iter().zip(combination)
+                .map(|(axis, value)| format!("{}={}", axis.key, format_value(value)))
+                .collect::<Vec<String>>()
+                .join(" ");
+            println!("[matrix] Run {}/{} ({}) repetition {} {}",
+                run, total, name, repetition, parameters);
+
+            // every run is a separate process so it gets a fresh testbed
+            let log_path = directory.join("output.log");
+            let log = File::create(&log_path)
+                .map_err(|e| format!("Could not create {}: {}", log_path.display(), e))?;
+            let stderr = log.try_clone().map_err(|e| e.to_string())?;
+            let started = unix_time();
+            let clock = Instant::now();
+            let status = Command::new(&exe)
+                .arg("--config")
+                .arg(&config_path)
+                .stdin(Stdio::null())
+                .stdout(log)
+                .stderr(stderr)
+                .status()
+                .map_err(|e| format!("Could not start run {}: {}", name, e))?;
+            let duration = clock.elapsed().as_secs_f64();
+
+            let exit_code = status.code()
+                .map(|code| code.to_string())
+                .unwrap_or(String::from("signal"));
+            if !status.success() {
+                failed += 1;
+                eprintln!("[matrix] Run {} failed ({}), see {}",
+                    name, exit_code, log_path.display());
+            }
+
+            let mut record = vec![run.to_string(), name, repetition.to_string()];
+            record.extend(combination.iter().map(format_value));
+            record.extend([started.to_string(), duration.to_string(), exit_code]);
+            manifest.write_record(&record)
+                .and_then(|_| manifest.flush().map_err(csv::Error::from))
+                .map_err(|e| format!("Could not write {}: {}", manifest_path.display(), e))?;
+        }
+    }
+
+    println!("[matrix] Finished {} runs, {} failed, manifest in {}",
+        total, failed, manifest_path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use toml::Value;
+
+    #[test]
+    fn parse_axis() {
+        let axis: Axis = "a.b=1,x".parse().unwrap();
+        assert_eq!(axis.key, "a.b");
+        assert_eq!(axis.values, [Value::Integer(1), Value::String(String::from("x"))]);
+
+        let axis: Axis = " netem.delay = 0.5, true ,,\"10ms\"".parse().unwrap();
+        assert_eq!(axis.key, "netem.delay");
+        assert_eq!(axis.values, [Value::Float(0.5), Value::Boolean(true),
+            Value::String(String::from("10ms"))]);
+
+        for s in ["a.b", "=1", "a..b=1", ".a=1", "a.=1", "a=", "a= , "] {
+            assert!(s.parse::<Axis>().is_err(), "{} was accepted", s);
+        }
+    }
+
+    #[test]
+    fn nested_keys() {
+        let mut table = toml::Table::new();
+        set_key(&mut table, "top", Value::Integer(1)).unwrap();
+        set_key(&mut table, "a.b.c", Value::Integer(2)).unwrap();
+        set_key(&mut table, "a.d", Value::Integer(3)).unwrap();
+        assert_eq!(table.to_string(), "top = 1\n\n[a]\nd = 3\n\n[a.b]\nc = 2\n");
+
+        assert_eq!(get_key(&table, "top"), Some(&Value::Integer(1)));
+        assert_eq!(get_key(&table, "a.b.c"), Some(&Value::Integer(2)));
+        assert_eq!(get_key(&table, "a.b.x"), None);
+        assert_eq!(get_key(&table, "top.x"), None);
+
+        // can't descend into a value
+        assert!(set_key(&mut table, "top.x", Value::Integer(4)).is_err());
+    }
+
+    #[test]
+    fn combination_order() {
+        let axes: Vec<Axis> = ["a=1,2", "b=x,y,z"].iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        let runs: Vec<String> = combinations(&axes).iter()
+            .map(|combination| combination.iter()
+                .map(format_value)
+                .collect::<Vec<String>>()
+                .join(" "))
+            .collect();
+        assert_eq!(runs, ["1 x", "1 y", "1 z", "2 x", "2 y", "2 z"]);
+
+        assert_eq!(combinations(&[]), [Vec::<Value>::new()]);
+    }
+
+    #[test]
+    fn run_config_paths() {
+        let base: toml::Table = toml::from_str(r#"
+            [trace]
+            file = "traces/up.csv"
+            downlink_file = "/srv/down.csv"
+
+            [output]
+            directory = "results"
+            events = "my-events.csv"
+        "#).unwrap();
+        let axes = vec!["trace.distribution=dist.csv".parse().unwrap()];
+        let combination = combinations(&axes).remove(0);
+        let base_dir = Path::new("/etc/experiments");
+
+        let config = run_config(&base, base_dir, &axes, &combination).unwrap();
+        let path = |key| get_key(&config, key).and_then(|value| value.as_str());
+        assert_eq!(path("trace.file"), Some("/etc/experiments/traces/up.csv"));
+        assert_eq!(path("trace.downlink_file"), Some("/srv/down.csv"));
+        assert_eq!(path("trace.distribution"), Some("/etc/experiments/dist.csv"));
+
+        // outputs are relative to the run directory
+        assert_eq!(path("output.directory"), None);
+        assert_eq!(path("output.events"), Some("my-events.csv"));
+        assert_eq!(path("output.metadata"), Some("metadata.json"));
+        assert_eq!(path("output.throughput"), Some("throughput.csv"));
+
+        // the base config is left alone
+        assert_eq!(get_key(&base, "trace.file").and_then(|value| value.as_str()),
+            Some("traces/up.csv"));
+    }
+}
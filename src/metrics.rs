@@ -0,0 +1,444 @@
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::ping;
+use crate::tcp_info::Side;
+
+/**
+ * Metrics settings shared by the workloads
+ */
+#[derive(Clone, Debug)]
+pub struct MetricsConfig {
+    /// file for throughput samples, not recorded if unset
+    pub throughput_file: Option<String>,
+    /// file for the trace event log, not recorded if unset
+    pub event_log_file: Option<String>,
+    /// file for every packet of the UDP probe, not recorded if unset
+    pub packet_log_file: Option<String>,
+    /// file for the events of the stream player, not recorded if unset
+    pub player_log_file: Option<String>,
+    /// file for RTT samples pinging ns1 from ns2, not probed if unset
+    pub ping_file: Option<String>,
+    /// time between two echo requests
+    pub ping_interval: Duration,
+    /// sample TCP_INFO of the test connections
+    /// written next to the throughput file
+    pub tcp_info: bool,
+    /// time between two samples
+    pub interval: Duration,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            throughput_file: None,
+            event_log_file: None,
+            packet_log_file: None,
+            player_log_file: None,
+            ping_file: None,
+            ping_interval: Duration::from_millis(ping::DEFAULT_INTERVAL),
+            tcp_info: false,
+            interval: Duration::from_millis(100),
+        }
+    }
+}
+
+/**
+ * Path of a file next to another one with the same extension
+ * e.g. results/throughput.csv, tcp_info_client -> results/tcp_info_client.csv
+ * @param path  Path of the other file
+ * @param name  File name without extension
+ */
+fn sibling(path: &str, name: &str) -> String {
+    let path = Path::new(path);
+    let extension = path.extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    path.with_file_name(format!("{}{}", name, extension)).to_string_lossy().into_owned()
+}
+
+impl MetricsConfig {
+    /**
+     * Path of the TCP_INFO samples for one side of the connection
+     * e.g. results/throughput.csv -> results/tcp_info_client.csv
+     * @param side  Client or server side
+     */
+    pub fn tcp_info_file(&self, side: Side) -> Option<String> {
+        if !self.tcp_info {
+            return None;
+        }
+        Some(sibling(self.throughput_file.as_ref()?, format!("tcp_info_{}", side).as_str()))
+    }
+
+    /**
+     * Path of the throughput samples of one direction of a bidirectional test
+     * e.g. results/throughput.csv -> results/throughput_download.csv
+     * @param direction  download or upload
+     */
+    pub fn direction_throughput_file(&self, direction: &str) -> Option<String> {
+        Some(sibling(self.throughput_file.as_ref()?, format!("throughput_{}", direction).as_str()))
+    }
+
+    /**
+     * Path of the per flow throughput samples
+     * e.g. results/throughput.csv -> results/flows.csv
+     */
+    pub fn flows_file(&self) -> Option<String> {
+        Some(sibling(self.throughput_file.as_ref()?, "flows"))
+    }
+
+    /**
+     * Path of the playback samples of the stream player
+     * e.g. results/player.csv -> results/playback.csv
+     */
+    pub fn playback_file(&self) -> Option<String> {
+        Some(sibling(self.player_log_file.as_ref()?, "playback"))
+    }
+
+    /**
+     * Path of the QoE summary of the stream player, always JSON
+     * e.g. results/player.csv -> results/qoe.json
+     */
+    pub fn qoe_file(&self) -> Option<String> {
+        let player_log = Path::new(self.player_log_file.as_ref()?);
+        Some(player_log.with_file_name("qoe.json").to_string_lossy().into_owned())
+    }
+}
+
+/**
+ * Output format of a metrics file
+ */
+enum MetricsFormat {
+    Csv(Box<csv::Writer<File>>),
+    JsonLines(BufWriter<File>),
+    /// array closed again after every sample, with the samples written so far
+    Json(File, usize),
+}
+
+/**
+ * Writes serializable samples to a file
+ * the format is picked from the extension:
+ * .jsonl for JSON Lines, .json for a JSON array, CSV otherwise
+ * Every sample is flushed right away because the
+ * workloads are killed at the end of the trace
+ */
+pub struct MetricsWriter {
+    path: String,
+    format: MetricsFormat,
+}
+
+impl MetricsWriter {
+    /**
+     * Create/truncate a metrics file
+     * @param path  Path to the file
+     */
+    pub fn create(path: &str) -> Result<Self, String> {
+        let mut file = File::create(path)
+            .map_err(|e| format!("Could not create metrics file {}: {}", path, e))?;
+        let format = if path.ends_with(".jsonl") {
+            MetricsFormat::JsonLines(BufWriter::new(file))
+        } else if path.ends_with(".json") {
+            // valid without any samples as well
+            file.write_all(b"[\n]\n")
+                .map_err(|e| format!("Could not write to metrics file {}: {}", path, e))?;
+            MetricsFormat::Json(file, 0)
+        } else {
+            MetricsFormat::Csv(Box::new(csv::Writer::from_writer(file)))
+        };
+
+        Ok(Self { path: String::from(path), format })
+    }
+
+    /**
+     * Append a sample
+     * @param sample  Sample to write, must serialize to a flat record
+     */
+    pub fn write<T: Serialize>(&mut self, sample: &T) -> Result<(), String> {
+        let result = match &mut self.format {
+            MetricsFormat::Csv(writer) => writer.serialize(sample)
+                .map_err(|e| e.to_string())
+                .and_then(|_| writer.flush().map_err(|e| e.to_string())),
+            MetricsFormat::JsonLines(writer) => serde_json::to_string(sample)
+                .map_err(|e| e.to_string())
+                .and_then(|line| writeln!(writer, "{}", line).map_err(|e| e.to_string()))
+                .and_then(|_| writer.flush().map_err(|e| e.to_string())),
+            // replace the closing bracket, or the newline before it to add a comma
+            MetricsFormat::Json(file, samples) => serde_json::to_string(sample)
+                .map_err(|e| e.to_string())
+                .and_then(|json| {
+                    let (offset, separator) = if *samples == 0 { (-2, "") } else { (-3, ",\n") };
+                    file.seek(SeekFrom::End(offset))
+                        .and_then(|_| file.write_all(format!("{}{}\n]\n", separator, json).as_bytes()))
+                        .map_err(|e| e.to_string())
+                })
+                .map(|_| *samples += 1),
+        };
+        result.map_err(|e| format!("Could not write to metrics file {}: {}", self.path, e))
+    }
+}
+
+/**
+ * Read all samples of a metrics file written by MetricsWriter
+ * the format is picked from the extension like when writing
+ * @param path  Path to the file
+ */
+pub fn read_samples<T: DeserializeOwned>(path: &str) -> Result<Vec<T>, String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("Could not read metrics file {}: {}", path, e))?;
+
+    if path.ends_with(".json") {
+        serde_json::from_str(content.as_str())
+            .map_err(|e| format!("Invalid samples in {}: {}", path, e))
+    } else if path.ends_with(".jsonl") {
+        content.lines()
+            .filter(|line| !line.trim().is_empty())
+            .enumerate()
+            .map(|(idx, line)| serde_json::from_str(line)
+                .map_err(|e| format!("Invalid sample on line {} of {}: {}", idx + 1, path, e)))
+            .collect()
+    } else {
+        csv::Reader::from_reader(content.as_bytes())
+            .deserialize()
+            .enumerate()
+            .map(|(idx, sample)| sample
+                .map_err(|e| format!("Invalid sample on line {} of {}: {}", idx + 2, path, e)))
+            .collect()
+    }
+}
+
+/**
+ * Seconds since the unix epoch for samples
+ */
+pub fn unix_time() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+/**
+ * A single throughput sample
+ */
+#[derive(Serialize)]
+struct ThroughputSample {
+    /// seconds since the start of the trace
+    time: f64,
+    /// seconds since the unix epoch
+    unix_time: f64,
+    /// bytes transferred since the last sample
+    bytes: u64,
+    /// bytes transferred since the start
+    total_bytes: u64,
+    /// rate since the last sample in mbit/s
+    rate: f64,
+}
+
+/**
+ * Throughput of a single flow when there are several
+ */
+#[derive(Serialize)]
+struct FlowSample {
+    /// seconds since the start of the trace
+    time: f64,
+    /// seconds since the unix epoch
+    unix_time: f64,
+    /// number of the flow, starting at 1
+    flow: usize,
+    /// bytes transferred since the last sample
+    bytes: u64,
+    /// bytes transferred since the start
+    total_bytes: u64,
+    /// rate since the last sample in mbit/s
+    rate: f64,
+    /// Jain's fairness index of all flows since the last sample
+    /// 1 if all got the same rate, empty if none transferred anything
+    fairness: Option<f64>,
+}
+
+/**
+ * Jain's fairness index (sum x)^2 / (n * sum x^2)
+ * @param rates  Rate of every flow
+ */
+pub fn jain_index(rates: &[f64]) -> Option<f64> {
+    let sum: f64 = rates.iter().sum();
+    let squares: f64 = rates.iter().map(|rate| rate * rate).sum();
+    if squares > 0.0 {
+        Some(sum * sum / (rates.len() as f64 * squares))
+    } else {
+        None
+    }
+}
+
+/**
+ * Counts transferred bytes and periodically writes
+ * a ThroughputSample to the throughput file
+ * Clones share the same counter
+ */
+#[derive(Clone)]
+pub struct ThroughputRecorder {
+    bytes: Arc<AtomicU64>,
+}
+
+impl ThroughputRecorder {
+    /**
+     * Create a recorder and start sampling
+     * Has to be called inside a tokio runtime
+     * @param config  Metrics config, nothing is written without a throughput file
+     * @param start   Start of the trace, samples are timed relative to it
+     *                so they line up with the trace timestamps
+     */
+    pub fn start(config: &MetricsConfig, start: Instant) -> Result<Self, String> {
+        Ok(Self::start_flows(config, start, 1)?.remove(0))
+    }
+
+    /**
+     * Create a recorder per flow and start sampling
+     * the throughput file gets the sum of all flows,
+     * with more than one flow every flow is written to the flows file
+     * Has to be called inside a tokio runtime
+     * @param config  Metrics config, nothing is written without a throughput file
+     * @param start   Start of the trace, samples are timed relative to it
+     * @param flows   Number of flows
+     */
+    pub fn start_flows(config: &MetricsConfig, start: Instant, flows: usize)
+        -> Result<Vec<Self>, String> {
+        let recorders: Vec<Self> = (0..flows)
+            .map(|_| Self { bytes: Arc::new(AtomicU64::new(0)) })
+            .collect();
+
+        if let Some(path) = &config.throughput_file {
+            let mut writer = MetricsWriter::create(path.as_str())?;
+            let mut flow_writer = config.flows_file()
+                .filter(|_| flows > 1)
+                .map(|path| MetricsWriter::create(path.as_str()))
+                .transpose()?;
+            let counters: Vec<Arc<AtomicU64>> = recorders.iter()
+                .map(|recorder| recorder.bytes.clone())
+                .collect();
+            let interval = config.interval;
+            println!("[metrics] Recording throughput every {}ms to {}",
+                interval.as_millis(), path);
+
+            tokio::spawn(async move {
+                let start = tokio::time::Instant::from_std(start);
+                let mut ticker = tokio::time::interval_at(start + interval, interval);
+                ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+                let mut last_time = start;
+                let mut last_bytes: Vec<u64> = vec![0; counters.len()];
+                loop {
+                    ticker.tick().await;
+                    let now = tokio::time::Instant::now();
+                    let flow_bytes: Vec<u64> = counters.iter()
+                        .map(|bytes| bytes.load(Ordering::Relaxed))
+                        .collect();
+                    let elapsed = (now - last_time).as_secs_f64();
+                    // rate in mbit/s
+                    let rate = |bytes: u64| (bytes as f64 * 8.0 / 1_000_000.0) / elapsed;
+
+                    let total_bytes: u64 = flow_bytes.iter().sum();
+                    let bytes = total_bytes - last_bytes.iter().sum::<u64>();
+                    let sample = ThroughputSample {
+                        time: (now - start).as_secs_f64(),
+                        unix_time: unix_time(),
+                        bytes,
+                        total_bytes,
+                        rate: rate(bytes),
+                    };
+                    if let Err(e) = writer.write(&sample) {
+                        eprintln!("[metrics] {}", e);
+                        return;
+                    }
+
+                    if let Some(flow_writer) = flow_writer.as_mut() {
+                        let rates: Vec<f64> = flow_bytes.iter().zip(&last_bytes)
+                            .map(|(total, last)| rate(total - last))
+                            .collect();
+                        let fairness = jain_index(&rates);
+                        for (flow, (total, last)) in flow_bytes.iter().zip(&last_bytes).enumerate() {
+                            let flow_sample = FlowSample {
+                                time: sample.time,
+                                unix_time: sample.unix_time,
+                                flow: flow + 1,
+                                bytes: total - last,
+                                total_bytes: *total,
+                                rate: rates[flow],
+                                fairness,
+                            };
+                            if let Err(e) = flow_writer.write(&flow_sample) {
+                                eprintln!("[metrics] {}", e);
+                                return;
+                            }
+                        }
+                    }
+
+                    last_time = now;
+                    last_bytes = flow_bytes;
+                }
+            });
+        }
+
+        Ok(recorders)
+    }
+
+    /**
+     * Count transferred bytes
+     * @param bytes  Number of bytes
+     */
+    pub fn add(&self, bytes: u64) {
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, serde::Deserialize)]
+    struct Sample {
+        time: f64,
+        bytes: u64,
+    }
+
+    #[test]
+    fn metrics_formats() {
+        let samples = [Sample { time: 0.1, bytes: 10 }, Sample { time: 0.2, bytes: 20 },
+            Sample { time: 0.3, bytes: 30 }];
+        for extension in ["csv", "jsonl", "json"] {
+            let path = std::env::temp_dir()
+                .join(format!("bridge-loss-emu-{}.{}", std::process::id(), extension));
+            let path = path.to_str().unwrap();
+
+            let mut writer = MetricsWriter::create(path).unwrap();
+            if extension == "json" {
+                assert_eq!(read_samples::<Sample>(path).unwrap(), []);
+            }
+            for (idx, sample) in samples.iter().enumerate() {
+                writer.write(sample).unwrap();
+                // readable after every sample
+                assert_eq!(read_samples::<Sample>(path).unwrap(), samples[..=idx]);
+            }
+            if extension == "json" {
+                let content = std::fs::read_to_string(path).unwrap();
+                assert!(serde_json::from_str::<serde_json::Value>(&content).unwrap().is_array());
+            }
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn jain_index_bounds() {
+        assert_eq!(jain_index(&[3.5, 3.5, 3.5, 3.5]), Some(1.0));
+        // one flow with everything
+        assert_eq!(jain_index(&[8.0, 0.0, 0.0, 0.0]), Some(0.25));
+        assert!((jain_index(&[1.0, 2.0, 3.0]).unwrap() - 36.0 / 42.0).abs() < 1e-12);
+        assert_eq!(jain_index(&[0.0, 0.0]), None);
+        assert_eq!(jain_index(&[]), None);
+    }
+}
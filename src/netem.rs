@@ -0,0 +1,879 @@
+use netlink_packet_utils::{byteorder::{ByteOrder, NativeEndian}, nla::DefaultNla, traits::Emitable};
+use netlink_packet_route::tc::TcOption;
+use serde::{Deserialize, Serialize, Serializer};
+use std::str::FromStr;
+
+/**
+ * consts from /include/uapi/linux/pkt_sched.h
+ * in the linux source tree
+ * these map to the "kind" of nla message
+ */
+const TCA_NETEM_UNSPEC: u16 = 0;
+const TCA_NETEM_CORR: u16 = 1;
+const TCA_NETEM_DELAY_DIST: u16 = 2;
+const TCA_NETEM_REORDER: u16 = 3;
+const TCA_NETEM_CORRUPT: u16 = 4;
+const TCA_NETEM_LOSS: u16 = 5;
+const TCA_NETEM_RATE: u16 = 6;
+const TCA_NETEM_ECN: u16 = 7;
+const TCA_NETEM_RATE64: u16 = 8;
+// const TCA_NETEM_PAD: u16 = 9;
+const TCA_NETEM_LATENCY64: u16 = 10;
+const TCA_NETEM_JITTER64: u16 = 11;
+const TCA_NETEM_SLOT: u16 = 12;
+// const TCA_NETEM_SLOT_DIST: u16 = 13;
+const TCA_NETEM_PRNG_SEED: u16 = 14;
+
+/**
+ * nested attributes of TCA_NETEM_LOSS
+ */
+const NETEM_LOSS_GI: u16 = 1;
+const NETEM_LOSS_GE: u16 = 2;
+
+/**
+ * Scale a percentage (0-100) to the u32 range used by netem
+ * @param percent  Percentage to scale
+ */
+fn percent_to_u32(percent: f32) -> u32 {
+    (u32::MAX as f64 * (percent as f64 / 100.0)) as u32
+}
+
+/**
+ * Loss models supported by netem
+ * all values are in percent (0-100)
+ * mirrors the "loss" syntax of tc-netem(8)
+ */
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "LossModelValue", into = "String")]
+pub enum LossModel {
+    /// independent random loss
+    Random {
+        loss: f32,
+    },
+    /// Gilbert-Elliott model
+    GilbertElliott {
+        /// transition probability good -> bad
+        p: f32,
+        /// transition probability bad -> good
+        r: f32,
+        /// loss probability in bad state (1-h)
+        loss_bad: f32,
+        /// loss probability in good state (1-k)
+        loss_good: f32,
+    },
+    /// 4-state Markov model
+    FourState {
+        /// transition probability good reception -> burst loss
+        p13: f32,
+        /// transition probability burst loss -> good reception
+        p31: f32,
+        /// transition probability burst loss -> good reception within burst
+        p32: f32,
+        /// transition probability good reception within burst -> burst loss
+        p23: f32,
+        /// transition probability good reception -> isolated loss
+        p14: f32,
+    },
+}
+
+impl LossModel {
+    /**
+     * Check that all parameters are in range [0..100]
+     */
+    pub fn validate(&self) -> Result<(), String> {
+        let params = match self {
+            LossModel::Random { loss } => vec![*loss],
+            LossModel::GilbertElliott { p, r, loss_bad, loss_good } =>
+                vec![*p, *r, *loss_bad, *loss_good],
+            LossModel::FourState { p13, p31, p32, p23, p14 } =>
+                vec![*p13, *p31, *p32, *p23, *p14],
+        };
+
+        for param in params {
+            if !(0.0..=100.0).contains(&param) {
+                return Err(format!("Loss parameter {} not in range [0..100] for {}",
+                        param, self));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for LossModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LossModel::Random { loss } => write!(f, "random {}%", loss),
+            LossModel::GilbertElliott { p, r, loss_bad, loss_good } =>
+                write!(f, "gemodel {}% {}% {}% {}%", p, r, loss_bad, loss_good),
+            LossModel::FourState { p13, p31, p32, p23, p14 } =>
+                write!(f, "state {}% {}% {}% {}% {}%", p13, p31, p32, p23, p14),
+        }
+    }
+}
+
+impl FromStr for LossModel {
+    type Err = String;
+
+    /**
+     * Parse a loss model from tc-netem(8) style syntax:
+     * PERCENT
+     * random PERCENT
+     * gemodel p [r [1-h [1-k]]]
+     * state p13 [p31 [p32 [p23 [p14]]]]
+     * Omitted parameters get the same defaults as in tc
+     */
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let kind = words.next().ok_or(String::from("Empty loss model"))?;
+
+        // bare percentage is random loss
+        if let Ok(loss) = f32::from_str(kind.trim_end_matches('%')) {
+            if words.next().is_some() {
+                return Err(format!("Unexpected parameters in loss model {}", s));
+            }
+            let model = LossModel::Random { loss };
+            model.validate()?;
+            return Ok(model);
+        }
+
+        let params = words
+            .map(|word| f32::from_str(word.trim_end_matches('%'))
+                .map_err(|_| format!("Could not parse f32 from: {} in loss model {}",
+                        word, s)))
+            .collect::<Result<Vec<f32>, String>>()?;
+
+        let max_params = match kind {
+            "random" => 1,
+            "gemodel" | "gilbert-elliott" => 4,
+            "state" | "4-state" => 5,
+            _ => return Err(format!("Unknown loss model {}", kind)),
+        };
+        if params.is_empty() || params.len() > max_params {
+            return Err(format!("Loss model {} takes 1 to {} parameters, got {}",
+                    kind, max_params, params.len()));
+        }
+        let param = |idx: usize, default: f32| params.get(idx).copied().unwrap_or(default);
+
+        let model = match kind {
+            "random" => LossModel::Random { loss: param(0, 0.0) },
+            "gemodel" | "gilbert-elliott" => LossModel::GilbertElliott {
+                p: param(0, 0.0),
+                r: param(1, 100.0 - param(0, 0.0)),
+                loss_bad: param(2, 100.0),
+                loss_good: param(3, 0.0),
+            },
+            _ => LossModel::FourState {
+                p13: param(0, 0.0),
+                p31: param(1, 100.0 - param(0, 0.0)),
+                p32: param(2, 0.0),
+                p23: param(3, 100.0),
+                p14: param(4, 0.0),
+            },
+        };
+        model.validate()?;
+        Ok(model)
+    }
+}
+
+/**
+ * Loss model in config files
+ * either a plain percentage or the tc-netem(8) syntax
+ */
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LossModelValue {
+    Percent(f32),
+    Model(String),
+}
+
+impl TryFrom<LossModelValue> for LossModel {
+    type Error = String;
+
+    fn try_from(value: LossModelValue) -> Result<Self, Self::Error> {
+        match value {
+            LossModelValue::Percent(loss) => {
+                let model = LossModel::Random { loss };
+                model.validate()?;
+                Ok(model)
+            },
+            LossModelValue::Model(s) => Self::from_str(s.as_str()),
+        }
+    }
+}
+
+impl From<LossModel> for String {
+    fn from(model: LossModel) -> Self {
+        model.to_string()
+    }
+}
+
+/**
+ * Slot based transmission
+ * packets are only sent in slots every min_delay to max_delay
+ * see "slot" in tc-netem(8)
+ */
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct NetemSlot {
+    /// minimum delay between slots in ns
+    pub min_delay: i64,
+    /// maximum delay between slots in ns
+    pub max_delay: i64,
+    /// maximum packets per slot (0 for unlimited)
+    pub max_packets: i32,
+    /// maximum bytes per slot (0 for unlimited)
+    pub max_bytes: i32,
+}
+
+impl std::fmt::Display for NetemSlot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{} ns", self.min_delay, self.max_delay)?;
+        if self.max_packets > 0 {
+            write!(f, " packets {}", self.max_packets)?;
+        }
+        if self.max_bytes > 0 {
+            write!(f, " bytes {}", self.max_bytes)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for NetemSlot {
+    type Err = String;
+
+    /**
+     * Parse a slot from "MIN_DELAY [MAX_DELAY [PACKETS [BYTES]]]"
+     * delays are in ms
+     */
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        if words.is_empty() || words.len() > 4 {
+            return Err(format!("Slot takes 1 to 4 parameters, got {}", words.len()));
+        }
+
+        let delay = |word: &str| f64::from_str(word)
+            .map(|ms| (ms * 1_000_000.0) as i64)
+            .map_err(|_| format!("Could not parse f64 from: {} in slot {}", word, s));
+        let count = |word: &str| i32::from_str(word)
+            .map_err(|_| format!("Could not parse i32 from: {} in slot {}", word, s));
+
+        let min_delay = delay(words[0])?;
+        Ok(Self {
+            min_delay,
+            max_delay: words.get(1).map(|word| delay(word)).transpose()?.unwrap_or(min_delay),
+            max_packets: words.get(2).map(|word| count(word)).transpose()?.unwrap_or(0),
+            max_bytes: words.get(3).map(|word| count(word)).transpose()?.unwrap_or(0),
+        })
+    }
+}
+
+impl Serialize for NetemSlot {
+    /**
+     * Serialize in the same "MIN_DELAY MAX_DELAY PACKETS BYTES" form
+     * that is parsed by from_str()
+     */
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(format!("{} {} {} {}",
+                self.min_delay as f64 / 1_000_000.0,
+                self.max_delay as f64 / 1_000_000.0,
+                self.max_packets,
+                self.max_bytes).as_str())
+    }
+}
+
+impl TryFrom<String> for NetemSlot {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::from_str(s.as_str())
+    }
+}
+
+/**
+ * Full configuration of a netem qdisc
+ * Build it like:
+ *   NetemConfig::new()
+ *       .limit(10_000)
+ *       .latency(18_000_000)
+ *       .loss(LossModel::Random { loss: 1.0 })
+ * Everything not set stays at the netem default (off)
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct NetemConfig {
+    limit: u32,
+    loss: LossModel,
+    loss_correlation: f32,
+    latency: i64,
+    jitter: i64,
+    latency_correlation: f32,
+    distribution: Option<Vec<i16>>,
+    reorder: f32,
+    reorder_correlation: f32,
+    gap: u32,
+    corrupt: f32,
+    corrupt_correlation: f32,
+    duplicate: f32,
+    duplicate_correlation: f32,
+    ecn: bool,
+    slot: Option<NetemSlot>,
+    seed: Option<u64>,
+    rate: u64,
+    packet_overhead: i32,
+    cell_size: u32,
+    cell_overhead: i32,
+}
+
+impl Default for NetemConfig {
+    fn default() -> Self {
+        Self {
+            limit: 1000, // same as tc
+            loss: LossModel::Random { loss: 0.0 },
+            loss_correlation: 0.0,
+            latency: 0,
+            jitter: 0,
+            latency_correlation: 0.0,
+            distribution: None,
+            reorder: 0.0,
+            reorder_correlation: 0.0,
+            gap: 0,
+            corrupt: 0.0,
+            corrupt_correlation: 0.0,
+            duplicate: 0.0,
+            duplicate_correlation: 0.0,
+            ecn: false,
+            slot: None,
+            seed: None,
+            rate: 0,
+            packet_overhead: 0,
+            cell_size: 0,
+            cell_overhead: 0,
+        }
+    }
+}
+
+impl NetemConfig {
+    /**
+     * Create a new NetemConfig with netem defaults
+     */
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /**
+     * @param limit  Limit for packets in queue
+     */
+    pub fn limit(mut self, limit: u32) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /**
+     * @param loss  Loss model
+     */
+    pub fn loss(mut self, loss: LossModel) -> Self {
+        self.loss = loss;
+        self
+    }
+
+    /**
+     * @param correlation  Loss correlation in % (random loss model only)
+     */
+    pub fn loss_correlation(mut self, correlation: f32) -> Self {
+        self.loss_correlation = correlation;
+        self
+    }
+
+    /**
+     * @param latency  Added delay in ns
+     */
+    pub fn latency(mut self, latency: i64) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /**
+     * @param jitter  Jitter for delay in ns
+     */
+    pub fn jitter(mut self, jitter: i64) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /**
+     * @param correlation  Delay correlation in %
+     */
+    pub fn latency_correlation(mut self, correlation: f32) -> Self {
+        self.latency_correlation = correlation;
+        self
+    }
+
+    /**
+     * @param distribution  Delay distribution table
+     *                      as obtained by get_distribution()
+     */
+    pub fn distribution(mut self, distribution: Vec<i16>) -> Self {
+        self.distribution = Some(distribution);
+        self
+    }
+
+    /**
+     * @param probability  Probability to send a packet immediately in %
+     * @param correlation  Reorder correlation in %
+     */
+    pub fn reorder(mut self, probability: f32, correlation: f32) -> Self {
+        self.reorder = probability;
+        self.reorder_correlation = correlation;
+        self
+    }
+
+    /**
+     * @param gap  Only reorder every gap-th packet
+     *             defaults to 1 if reordering is enabled
+     */
+    pub fn gap(mut self, gap: u32) -> Self {
+        self.gap = gap;
+        self
+    }
+
+    /**
+     * @param probability  Probability to corrupt a packet in %
+     * @param correlation  Corrupt correlation in %
+     */
+    pub fn corrupt(mut self, probability: f32, correlation: f32) -> Self {
+        self.corrupt = probability;
+        self.corrupt_correlation = correlation;
+        self
+    }
+
+    /**
+     * @param probability  Probability to duplicate a packet in %
+     * @param correlation  Duplicate correlation in %
+     */
+    pub fn duplicate(mut self, probability: f32, correlation: f32) -> Self {
+        self.duplicate = probability;
+        self.duplicate_correlation = correlation;
+        self
+    }
+
+    /**
+     * @param ecn  Mark packets with ECN instead of dropping them
+     */
+    pub fn ecn(mut self, ecn: bool) -> Self {
+        self.ecn = ecn;
+        self
+    }
+
+    /**
+     * @param slot  Slot based transmission
+     */
+    pub fn slot(mut self, slot: NetemSlot) -> Self {
+        self.slot = Some(slot);
+        self
+    }
+
+    /**
+     * @param seed  Seed for the netem PRNG to make loss reproducible
+     */
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /**
+     * @param rate  Rate limit in byte/s
+     */
+    pub fn rate(mut self, rate: u64) -> Self {
+        self.rate = rate;
+        self
+    }
+
+    /**
+     * @param packet_overhead  Per packet overhead in bytes (may be negative)
+     * @param cell_size        Link layer cell size in bytes (0 for none)
+     * @param cell_overhead    Per cell overhead in bytes (may be negative)
+     */
+    pub fn rate_overhead(mut self, packet_overhead: i32, cell_size: u32, cell_overhead: i32) -> Self {
+        self.packet_overhead = packet_overhead;
+        self.cell_size = cell_size;
+        self.cell_overhead = cell_overhead;
+        self
+    }
+
+    /**
+     * Check that all parameters are in range
+     */
+    pub fn validate(&self) -> Result<(), String> {
+        self.loss.validate()?;
+
+        for (name, value) in [
+            ("loss correlation", self.loss_correlation),
+            ("latency correlation", self.latency_correlation),
+            ("reorder", self.reorder),
+            ("reorder correlation", self.reorder_correlation),
+            ("corrupt", self.corrupt),
+            ("corrupt correlation", self.corrupt_correlation),
+            ("duplicate", self.duplicate),
+            ("duplicate correlation", self.duplicate_correlation),
+        ] {
+            if !(0.0..=100.0).contains(&value) {
+                return Err(format!("{} value {} not in range [0..100]", name, value));
+            }
+        }
+
+        if self.latency < 0 || self.jitter < 0 {
+            return Err(format!("Negative latency {} ns or jitter {} ns",
+                    self.latency, self.jitter));
+        }
+
+        if let Some(slot) = self.slot.as_ref()
+            .filter(|slot| slot.min_delay < 0 || slot.max_delay < slot.min_delay) {
+            return Err(format!("Invalid slot delays {}", slot));
+        }
+
+        Ok(())
+    }
+
+    /**
+     * Serialize this config into netem options
+     * these emulate the structs from
+     * /include/uapi/linux/pkt_sched.h
+     * may be x86 exclusive because of this but whatever
+     *
+     * types of standalone attributes are from
+     * /net/sched/sch_netem.c
+     */
+    pub fn to_options(&self) -> Vec<TcOption> {
+        let mut options: Vec<TcOption> = Vec::new();
+
+        // tc does the same if reordering is requested without a gap
+        let gap = if self.reorder > 0.0 && self.gap == 0 { 1 } else { self.gap };
+
+        // tc_netem_qopt
+        let kind = TCA_NETEM_UNSPEC;
+        let mut value = vec![0; 20];
+        //__u32	latency;	/* added delay (us) */
+        // 1st in tc_netem_qopts struct but not parsed?
+        //__u32   limit;		/* fifo limit (packets) */
+        NativeEndian::write_u32(&mut value[0..4], self.limit);
+        //__u32	loss;		/* random packet loss (0=none ~0=100%) */
+        // only used by the random loss model
+        // other models are set via TCA_NETEM_LOSS
+        let random_loss = match self.loss {
+            LossModel::Random { loss } => loss,
+            _ => 0.0,
+        };
+        NativeEndian::write_u32(&mut value[4..8], percent_to_u32(random_loss));
+        //__u32	gap;		/* re-ordering gap (0 for none) */
+        NativeEndian::write_u32(&mut value[8..12], gap);
+        //__u32   duplicate;	/* random packet dup  (0=none ~0=100%) */
+        NativeEndian::write_u32(&mut value[12..16], percent_to_u32(self.duplicate));
+        //__u32	jitter;		/* random jitter in latency (us) */
+        // skewed by like << 6 bitshift or sth idk
+        NativeEndian::write_u32(&mut value[16..20], 0);
+        options.push(TcOption::Other(DefaultNla::new(kind, value)));
+
+        // tc_netem_corr
+        let kind = TCA_NETEM_CORR;
+        let mut value = vec![0; 12];
+        //__u32	delay_corr;	/* delay correlation */
+        NativeEndian::write_u32(&mut value[0..4], percent_to_u32(self.latency_correlation));
+        //__u32	loss_corr;	/* packet loss correlation */
+        NativeEndian::write_u32(&mut value[4..8], percent_to_u32(self.loss_correlation));
+        //__u32	dup_corr;	/* duplicate correlation  */
+        NativeEndian::write_u32(&mut value[8..12], percent_to_u32(self.duplicate_correlation));
+        options.push(TcOption::Other(DefaultNla::new(kind, value)));
+
+        // distribution data for delays
+        // as a vector of i16
+        // not sending this keeps the current table
+        if let Some(distribution) = &self.distribution {
+            let kind = TCA_NETEM_DELAY_DIST;
+            let mut value = vec![0; 2 * distribution.len()];
+            let mut start = 0;
+            for entry in distribution {
+                NativeEndian::write_i16(&mut value[start..start+2], *entry);
+                start += 2;
+            }
+            options.push(TcOption::Other(DefaultNla::new(kind, value)));
+        }
+
+        // loss model as nested attributes
+        // not sending this resets the kernel to the random loss model
+        match self.loss {
+            LossModel::Random { .. } => {},
+            LossModel::GilbertElliott { p, r, loss_bad, loss_good } => {
+                // tc_netem_gemodel
+                let mut model = vec![0; 16];
+                //__u32 p;
+                NativeEndian::write_u32(&mut model[0..4], percent_to_u32(p));
+                //__u32 r;
+                NativeEndian::write_u32(&mut model[4..8], percent_to_u32(r));
+                //__u32 h;
+                // tc takes 1-h but the kernel expects h
+                NativeEndian::write_u32(&mut model[8..12], percent_to_u32(100.0 - loss_bad));
+                //__u32 k1;
+                NativeEndian::write_u32(&mut model[12..16], percent_to_u32(loss_good));
+                options.push(TcOption::Other(
+                        nested_nla(TCA_NETEM_LOSS, NETEM_LOSS_GE, model)));
+            },
+            LossModel::FourState { p13, p31, p32, p23, p14 } => {
+                // tc_netem_gimodel
+                let mut model = vec![0; 20];
+                //__u32 p13;
+                NativeEndian::write_u32(&mut model[0..4], percent_to_u32(p13));
+                //__u32 p31;
+                NativeEndian::write_u32(&mut model[4..8], percent_to_u32(p31));
+                //__u32 p32;
+                NativeEndian::write_u32(&mut model[8..12], percent_to_u32(p32));
+                //__u32 p14;
+                NativeEndian::write_u32(&mut model[12..16], percent_to_u32(p14));
+                //__u32 p23;
+                NativeEndian::write_u32(&mut model[16..20], percent_to_u32(p23));
+                options.push(TcOption::Other(
+                        nested_nla(TCA_NETEM_LOSS, NETEM_LOSS_GI, model)));
+            },
+        }
+
+        // tc_netem_reorder
+        let kind = TCA_NETEM_REORDER;
+        let mut value = vec![0; 8];
+        //__u32	probability;
+        NativeEndian::write_u32(&mut value[0..4], percent_to_u32(self.reorder));
+        //__u32	correlation;
+        NativeEndian::write_u32(&mut value[4..8], percent_to_u32(self.reorder_correlation));
+        options.push(TcOption::Other(DefaultNla::new(kind, value)));
+
+        // tc_netem_corrupt {
+        let kind = TCA_NETEM_CORRUPT;
+        let mut value = vec![0; 8];
+        //__u32	probability;
+        NativeEndian::write_u32(&mut value[0..4], percent_to_u32(self.corrupt));
+        //__u32	correlation;
+        NativeEndian::write_u32(&mut value[4..8], percent_to_u32(self.corrupt_correlation));
+        options.push(TcOption::Other(DefaultNla::new(kind, value)));
+
+        // tc_netem_rate
+        let kind = TCA_NETEM_RATE;
+        let mut value = vec![0; 16];
+        //__u32	rate;	/* byte/s */
+        NativeEndian::write_u32(&mut value[0..4], 0);
+        //__s32	packet_overhead;
+        NativeEndian::write_i32(&mut value[4..8], self.packet_overhead);
+        //__u32	cell_size;
+        NativeEndian::write_u32(&mut value[8..12], self.cell_size);
+        //__s32	cell_overhead;
+        NativeEndian::write_i32(&mut value[12..16], self.cell_overhead);
+        options.push(TcOption::Other(DefaultNla::new(kind, value)));
+
+        // ECN marking instead of dropping
+        // the kernel keeps the old value if not sent
+        let kind = TCA_NETEM_ECN;
+        let mut value = vec![0; 4];
+        NativeEndian::write_u32(&mut value[0..4], self.ecn as u32);
+        options.push(TcOption::Other(DefaultNla::new(kind, value)));
+
+        // tc_netem_slot
+        // all zeros disables slotting
+        let slot = self.slot.clone().unwrap_or_default();
+        let kind = TCA_NETEM_SLOT;
+        let mut value = vec![0; 40];
+        //__s64   min_delay; /* nsec */
+        NativeEndian::write_i64(&mut value[0..8], slot.min_delay);
+        //__s64   max_delay;
+        NativeEndian::write_i64(&mut value[8..16], slot.max_delay);
+        //__s32   max_packets;
+        NativeEndian::write_i32(&mut value[16..20], slot.max_packets);
+        //__s32   max_bytes;
+        NativeEndian::write_i32(&mut value[20..24], slot.max_bytes);
+        //__s64	dist_delay; /* nsec */
+        NativeEndian::write_i64(&mut value[24..32], 0);
+        //__s64	dist_jitter; /* nsec */
+        NativeEndian::write_i64(&mut value[32..40], 0);
+        options.push(TcOption::Other(DefaultNla::new(kind, value)));
+
+        // PRNG seed
+        // the kernel picks a random one if not sent
+        if let Some(seed) = self.seed {
+            let kind = TCA_NETEM_PRNG_SEED;
+            let mut value = vec![0; 8];
+            NativeEndian::write_u64(&mut value[0..8], seed);
+            options.push(TcOption::Other(DefaultNla::new(kind, value)));
+        }
+
+        // 64 bit version of rate in byte/s
+        // max of tc_netem_rate.rate and this is picked
+        // (meaning always this because the prior is 0)
+        let kind = TCA_NETEM_RATE64;
+        let mut value = vec![0; 8];
+        NativeEndian::write_u64(&mut value[0..8], self.rate);
+        options.push(TcOption::Other(DefaultNla::new(kind, value)));
+
+        // 64 bit version of latency in ns
+        // considering tc_netem_qopt.latency doesn't seem to exist anymore
+        // (makes nla invalid) just use this
+        // why is it signed? I have no idea
+        let kind = TCA_NETEM_LATENCY64;
+        let mut value = vec![0; 8];
+        NativeEndian::write_i64(&mut value[0..8], self.latency);
+        options.push(TcOption::Other(DefaultNla::new(kind, value)));
+
+        // 64 bit version of jitter in ns
+        // same as TCA_NETEM_LATENCY64 this is the more supported version
+        let kind = TCA_NETEM_JITTER64;
+        let mut value = vec![0; 8];
+        NativeEndian::write_i64(&mut value[0..8], self.jitter);
+        options.push(TcOption::Other(DefaultNla::new(kind, value)));
+
+        options
+    }
+}
+
+impl std::fmt::Display for NetemConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "limit: {} pkts, loss: {}, rate: {} byte/s, \
+            latency: {} ns, jitter: {} ns",
+            self.limit, self.loss, self.rate, self.latency, self.jitter)?;
+        if self.reorder > 0.0 {
+            write!(f, ", reorder: {}%", self.reorder)?;
+        }
+        if self.corrupt > 0.0 {
+            write!(f, ", corrupt: {}%", self.corrupt)?;
+        }
+        if self.duplicate > 0.0 {
+            write!(f, ", duplicate: {}%", self.duplicate)?;
+        }
+        if self.ecn {
+            write!(f, ", ecn")?;
+        }
+        if let Some(slot) = &self.slot {
+            write!(f, ", slot: {}", slot)?;
+        }
+        if let Some(seed) = self.seed {
+            write!(f, ", seed: {}", seed)?;
+        }
+        Ok(())
+    }
+}
+
+/**
+ * Wrap a single attribute in a nested attribute
+ * @param outer  Kind of the nesting attribute
+ * @param inner  Kind of the nested attribute
+ * @param value  Payload of the nested attribute
+ */
+fn nested_nla(outer: u16, inner: u16, value: Vec<u8>) -> DefaultNla {
+    let nested = [DefaultNla::new(inner, value)];
+    let mut buf = vec![0; nested.as_slice().buffer_len()];
+    nested.as_slice().emit(&mut buf);
+    DefaultNla::new(outer, buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use netlink_packet_utils::nla::Nla;
+
+    /**
+     * Payload of the first netem option of a kind
+     * @param options  Options as built by to_options()
+     * @param kind     TCA_NETEM_* kind
+     */
+    fn option(options: &[TcOption], kind: u16) -> Option<Vec<u8>> {
+        options.iter().find_map(|option| match option {
+            TcOption::Other(nla) if nla.kind() == kind => {
+                let mut value = vec![0; nla.value_len()];
+                nla.emit_value(&mut value);
+                Some(value)
+            },
+            _ => None,
+        })
+    }
+
+    /**
+     * Native endian u32 fields of a kernel struct
+     */
+    fn fields(value: &[u8]) -> Vec<u32> {
+        value.chunks(4).map(NativeEndian::read_u32).collect()
+    }
+
+    #[test]
+    fn parse_loss_models() {
+        assert_eq!("5%".parse(), Ok(LossModel::Random { loss: 5.0 }));
+        assert_eq!("random 2.5".parse(), Ok(LossModel::Random { loss: 2.5 }));
+        assert_eq!("gemodel 1% 10% 70% 0.1%".parse(), Ok(LossModel::GilbertElliott {
+            p: 1.0, r: 10.0, loss_bad: 70.0, loss_good: 0.1 }));
+        assert_eq!("state 1 2 3 4 5".parse(), Ok(LossModel::FourState {
+            p13: 1.0, p31: 2.0, p32: 3.0, p23: 4.0, p14: 5.0 }));
+
+        // same defaults as tc
+        assert_eq!("gilbert-elliott 1".parse(), Ok(LossModel::GilbertElliott {
+            p: 1.0, r: 99.0, loss_bad: 100.0, loss_good: 0.0 }));
+        assert_eq!("4-state 10".parse(), Ok(LossModel::FourState {
+            p13: 10.0, p31: 90.0, p32: 0.0, p23: 100.0, p14: 0.0 }));
+
+        // Display writes the same syntax
+        for s in ["random 3%", "gemodel 1% 10% 70% 0.1%", "state 1% 2% 3% 4% 5%"] {
+            let model: LossModel = s.parse().unwrap();
+            assert_eq!(model.to_string(), s);
+        }
+    }
+
+    #[test]
+    fn parse_loss_model_errors() {
+        for s in ["", "5 6", "gemodel", "gemodel 1 2 3 4 5", "state 1 2 3 4 5 6",
+            "state x", "bursty 1", "gemodel 101", "-1", "random 1 2"] {
+            assert!(s.parse::<LossModel>().is_err(), "{} was accepted", s);
+        }
+    }
+
+    #[test]
+    fn random_loss_options() {
+        let options = NetemConfig::new()
+            .limit(500)
+            .loss(LossModel::Random { loss: 100.0 })
+            .to_options();
+        // tc_netem_qopt: limit, loss, gap, duplicate, jitter
+        assert_eq!(fields(&option(&options, TCA_NETEM_UNSPEC).unwrap()),
+            [500, u32::MAX, 0, 0, 0]);
+        // the kernel would switch to the random model without TCA_NETEM_LOSS anyway
+        assert_eq!(option(&options, TCA_NETEM_LOSS), None);
+    }
+
+    #[test]
+    fn gemodel_options() {
+        let options = NetemConfig::new()
+            .loss(LossModel::GilbertElliott { p: 1.0, r: 10.0, loss_bad: 70.0, loss_good: 0.1 })
+            .to_options();
+        // the random loss in tc_netem_qopt is off
+        assert_eq!(fields(&option(&options, TCA_NETEM_UNSPEC).unwrap())[1], 0);
+
+        // nested NETEM_LOSS_GE header (length, type) and tc_netem_gemodel { p, r, h, k1 }
+        let loss = option(&options, TCA_NETEM_LOSS).unwrap();
+        assert_eq!(loss.len(), 20);
+        assert_eq!(NativeEndian::read_u16(&loss[0..2]), 20);
+        assert_eq!(NativeEndian::read_u16(&loss[2..4]), NETEM_LOSS_GE);
+        assert_eq!(fields(&loss[4..]), [percent_to_u32(1.0), percent_to_u32(10.0),
+            percent_to_u32(30.0), percent_to_u32(0.1)]);
+    }
+
+    #[test]
+    fn gimodel_options() {
+        let options = NetemConfig::new()
+            .loss(LossModel::FourState { p13: 1.0, p31: 2.0, p32: 3.0, p23: 4.0, p14: 5.0 })
+            .to_options();
+
+        // nested NETEM_LOSS_GI header and tc_netem_gimodel { p13, p31, p32, p14, p23 }
+        let loss = option(&options, TCA_NETEM_LOSS).unwrap();
+        assert_eq!(loss.len(), 24);
+        assert_eq!(NativeEndian::read_u16(&loss[0..2]), 24);
+        assert_eq!(NativeEndian::read_u16(&loss[2..4]), NETEM_LOSS_GI);
+        assert_eq!(fields(&loss[4..]), [1.0, 2.0, 3.0, 5.0, 4.0].map(percent_to_u32));
+    }
+
+    #[test]
+    fn percent_scale() {
+        assert_eq!(percent_to_u32(0.0), 0);
+        assert_eq!(percent_to_u32(100.0), u32::MAX);
+        assert_eq!(percent_to_u32(50.0), u32::MAX / 2);
+    }
+}
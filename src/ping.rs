@@ -0,0 +1,236 @@
+use serde::Serialize;
+use std::net::IpAddr;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::time::{Duration, Instant};
+
+use crate::metrics::{unix_time, MetricsConfig, MetricsWriter};
+use crate::testbed::Testbed;
+
+/// default time between two echo requests in ms
+pub const DEFAULT_INTERVAL: u64 = 100;
+
+/**
+ * ICMP message types
+ */
+const ICMP_ECHO_REPLY: u8 = 0;
+const ICMP_ECHO_REQUEST: u8 = 8;
+const ICMPV6_ECHO_REQUEST: u8 = 128;
+const ICMPV6_ECHO_REPLY: u8 = 129;
+
+/**
+ * ICMP echo header (type, code, checksum, identifier, sequence number)
+ * followed by our payload: sequence number and send time in ns
+ * since the trace start, both big endian
+ */
+const ICMP_HEADER_LEN: usize = 8;
+const PAYLOAD_LEN: usize = 16;
+
+/**
+ * A single answered echo request
+ */
+#[derive(Serialize)]
+struct PingSample {
+    seq: u64,
+    /// seconds since the start of the trace the request was sent at
+    time: f64,
+    /// seconds since the start of the trace the reply arrived at
+    received: f64,
+    /// seconds since the unix epoch
+    unix_time: f64,
+    /// round trip time in ms
+    rtt: f64,
+}
+
+/**
+ * Internet checksum (RFC 1071)
+ * @param data  ICMP message with a zeroed checksum field
+ */
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data.chunks(2)
+        .map(|chunk| u16::from_be_bytes([chunk[0], *chunk.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/**
+ * Open a raw ICMP or ICMPv6 socket
+ * needs CAP_NET_RAW
+ * @param ipv6  Open an ICMPv6 socket
+ */
+fn open_socket(ipv6: bool) -> Result<OwnedFd, String> {
+    let fd = unsafe {
+        if ipv6 {
+            libc::socket(libc::AF_INET6, libc::SOCK_RAW, libc::IPPROTO_ICMPV6)
+        } else {
+            libc::socket(libc::AF_INET, libc::SOCK_RAW, libc::IPPROTO_ICMP)
+        }
+    };
+    if fd < 0 {
+        return Err(format!("Could not open ICMP socket: {}", std::io::Error::last_os_error()));
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/**
+ * Send an echo request
+ * the kernel fills in the checksum for ICMPv6
+ * @param fd      Raw ICMP socket
+ * @param target  Address to ping
+ * @param id      Identifier of our requests
+ * @param seq     Sequence number
+ * @param sent    Send time since the trace start
+ */
+fn send_request(fd: &OwnedFd, target: IpAddr, id: u16, seq: u64, sent: Duration) -> std::io::Result<()> {
+    let mut packet = [0u8; ICMP_HEADER_LEN + PAYLOAD_LEN];
+    packet[0] = if target.is_ipv6() { ICMPV6_ECHO_REQUEST } else { ICMP_ECHO_REQUEST };
+    packet[4..6].copy_from_slice(&id.to_be_bytes());
+    packet[6..8].copy_from_slice(&(seq as u16).to_be_bytes());
+    packet[8..16].copy_from_slice(&seq.to_be_bytes());
+    packet[16..24].copy_from_slice(&(sent.as_nanos() as u64).to_be_bytes());
+    if target.is_ipv4() {
+        let sum = checksum(&packet);
+        packet[2..4].copy_from_slice(&sum.to_be_bytes());
+    }
+
+    let ret = match target {
+        IpAddr::V4(addr) => {
+            let mut sockaddr: libc::sockaddr_in = unsafe { std::mem::zeroed() };
+            sockaddr.sin_family = libc::AF_INET as libc::sa_family_t;
+            sockaddr.sin_addr.s_addr = u32::from(addr).to_be();
+            unsafe {
+                libc::sendto(fd.as_raw_fd(), packet.as_ptr() as *const libc::c_void, packet.len(), 0,
+                    &sockaddr as *const _ as *const libc::sockaddr,
+                    std::mem::size_of::<libc::sockaddr_in>() as libc::socklen_t)
+            }
+        },
+        IpAddr::V6(addr) => {
+            let mut sockaddr: libc::sockaddr_in6 = unsafe { std::mem::zeroed() };
+            sockaddr.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+            sockaddr.sin6_addr.s6_addr = addr.octets();
+            unsafe {
+                libc::sendto(fd.as_raw_fd(), packet.as_ptr() as *const libc::c_void, packet.len(), 0,
+                    &sockaddr as *const _ as *const libc::sockaddr,
+                    std::mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t)
+            }
+        },
+    };
+    if ret < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/**
+ * Parse an echo reply to one of our requests
+ * raw ICMP sockets get the IPv4 header, ICMPv6 sockets don't
+ * @param packet  Received packet
+ * @param ipv6    Received on an ICMPv6 socket
+ * @param id      Identifier of our requests
+ * @return (sequence number, send time since the trace start)
+ */
+fn parse_reply(packet: &[u8], ipv6: bool, id: u16) -> Option<(u64, Duration)> {
+    let icmp = if ipv6 {
+        packet
+    } else {
+        packet.get((*packet.first()? & 0x0f) as usize * 4..)?
+    };
+    let reply = if ipv6 { ICMPV6_ECHO_REPLY } else { ICMP_ECHO_REPLY };
+    if icmp.len() < ICMP_HEADER_LEN + PAYLOAD_LEN
+        || icmp[0] != reply
+        || u16::from_be_bytes([icmp[4], icmp[5]]) != id {
+        return None;
+    }
+    let seq = u64::from_be_bytes(icmp[8..16].try_into().unwrap());
+    let sent = Duration::from_nanos(u64::from_be_bytes(icmp[16..24].try_into().unwrap()));
+    Some((seq, sent))
+}
+
+/**
+ * Send ICMP echo requests at a fixed interval and log the RTT of every reply
+ * Lost requests show up as missing sequence numbers
+ * runs until the process is killed
+ * @param target    Address to ping
+ * @param interval  Time between two requests
+ * @param start     Start of the trace, times are relative to it
+ * @param log       File the samples are written to
+ */
+pub fn run(target: IpAddr, interval: Duration, start: Instant, log: &str) -> Result<(), String> {
+    let mut writer = MetricsWriter::create(log)?;
+    let fd = open_socket(target.is_ipv6())?;
+    let id = std::process::id() as u16;
+    println!("[ping] Pinging {} every {}ms", target, interval.as_millis());
+
+    let mut buf = [0u8; 1500];
+    let first = Instant::now();
+    for seq in 0u64.. {
+        // collect replies until the next request is due
+        let due = first + interval.mul_f64(seq as f64);
+        loop {
+            let now = Instant::now();
+            if now >= due {
+                break;
+            }
+            let mut pollfd = libc::pollfd { fd: fd.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+            let timeout = (due - now).as_millis() as libc::c_int + 1;
+            if unsafe { libc::poll(&mut pollfd, 1, timeout) } <= 0 {
+                continue;
+            }
+
+            let len = unsafe {
+                libc::recv(fd.as_raw_fd(), buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(), libc::MSG_DONTWAIT)
+            };
+            let received = start.elapsed();
+            if len <= 0 {
+                continue;
+            }
+            if let Some((reply_seq, sent)) = parse_reply(&buf[..len as usize], target.is_ipv6(), id) {
+                writer.write(&PingSample {
+                    seq: reply_seq,
+                    time: sent.as_secs_f64(),
+                    received: received.as_secs_f64(),
+                    unix_time: unix_time(),
+                    rtt: received.saturating_sub(sent).as_secs_f64() * 1000.0,
+                })?;
+            }
+        }
+
+        match send_request(&fd, target, id, seq, start.elapsed()) {
+            Ok(_) => (),
+            // no route or full queue, the request counts as lost
+            Err(e) if matches!(e.raw_os_error(),
+                Some(libc::ENOBUFS) | Some(libc::ENETUNREACH) | Some(libc::EHOSTUNREACH)) => (),
+            Err(e) => return Err(format!("Pinging {} failed: {}", target, e)),
+        }
+    }
+    Ok(())
+}
+
+/**
+ * Ping the server in ns1 from ns2 next to the workload
+ * if a ping file is configured
+ * @param testbed  Testbed of the run
+ * @param metrics  Metrics settings with the ping file and interval
+ * @param start    Start of the trace
+ */
+pub fn start_prober(testbed: &Testbed, metrics: &MetricsConfig, start: Instant) {
+    let Some(log) = metrics.ping_file.clone() else {
+        return;
+    };
+    if let Err(e) = testbed.fork_child("ping", || {
+        let _ = testbed.ns2.run(|_| {
+            let target = testbed.server_host()
+                .trim_matches(['[', ']'])
+                .parse()
+                .expect("[ping] invalid server address");
+            if let Err(e) = run(target, metrics.ping_interval, start, log.as_str()) {
+                eprintln!("[ping] {}", e);
+            }
+        });
+    }) {
+        eprintln!("[test] {}", e);
+    }
+}
@@ -0,0 +1,453 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::metrics::{read_samples, unix_time, MetricsConfig, MetricsWriter};
+
+/// default playout buffer the player fills up to in s
+pub const DEFAULT_BUFFER: f64 = 30.0;
+
+/**
+ * Segments that have to be buffered before playback starts or resumes
+ */
+const START_SEGMENTS: f64 = 2.0;
+
+/**
+ * Share of the estimated throughput the picked bitrate may use
+ */
+const SAFETY_FACTOR: f64 = 0.8;
+
+/**
+ * Number of segments the throughput estimate is averaged over
+ */
+const HISTORY: usize = 5;
+
+/**
+ * Description of the video the segment server offers
+ */
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Manifest {
+    /// playback duration of a segment in s
+    pub segment_duration: f64,
+    /// bitrate of every level in kbit/s, ascending
+    pub bitrates: Vec<u64>,
+}
+
+/**
+ * What happened in the player
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlayerEvent {
+    /// a segment was downloaded
+    Segment,
+    /// playback started for the first time
+    Startup,
+    /// the next segment is requested at another bitrate
+    Switch,
+    /// the buffer ran empty and playback stopped
+    Stall,
+    /// playback continued after a stall
+    Resume,
+}
+
+/**
+ * A single entry of the player log
+ */
+#[derive(Deserialize, Serialize)]
+struct PlayerSample {
+    /// seconds since the start of the trace
+    time: f64,
+    /// seconds since the unix epoch
+    unix_time: f64,
+    event: PlayerEvent,
+    /// bitrate of the current level in kbit/s
+    bitrate: u64,
+    /// buffered playback in s
+    buffer: f64,
+    /// download time of a segment, startup delay or stall duration in s
+    duration: Option<f64>,
+    /// throughput of a segment download in mbit/s
+    throughput: Option<f64>,
+}
+
+/**
+ * State of the playback
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlaybackState {
+    /// waiting for the first segments
+    Startup,
+    Playing,
+    /// waiting for segments after the buffer ran empty
+    Stalled,
+}
+
+/**
+ * Periodic sample of the playback
+ */
+#[derive(Serialize)]
+struct PlaybackSample {
+    /// seconds since the start of the trace
+    time: f64,
+    /// seconds since the unix epoch
+    unix_time: f64,
+    state: PlaybackState,
+    /// playback position in s
+    position: f64,
+    /// buffered playback in s
+    buffer: f64,
+    /// bitrate of the current level in kbit/s
+    bitrate: u64,
+}
+
+/**
+ * Quality of experience of a whole run
+ */
+#[derive(Debug, Deserialize, Serialize)]
+pub struct QoeSummary {
+    /// seconds from opening the video until playback started
+    /// None if it never started
+    pub startup_delay: Option<f64>,
+    pub stalls: usize,
+    /// seconds spent in stalls, an unfinished stall counts until the end
+    pub stall_time: f64,
+    /// share of the time after startup spent in stalls
+    pub stall_ratio: f64,
+    /// playback position at the end in s
+    pub played: f64,
+    pub segments: usize,
+    /// mean bitrate of the segments in kbit/s
+    pub mean_bitrate: f64,
+    pub switches: usize,
+    /// mean bitrate change of a switch in kbit/s
+    pub mean_switch: f64,
+}
+
+/**
+ * Playout buffer of a video player with throughput based bitrate adaptation
+ * Playback is simulated, the buffer drains in real time while playing
+ * All times are seconds since the start of the trace
+ */
+pub struct Player {
+    manifest: Manifest,
+    max_buffer: f64,
+    /// level of the next segment
+    level: usize,
+    /// buffered playback in s
+    buffer: f64,
+    playing: bool,
+    started: bool,
+    /// playback position in s
+    position: f64,
+    /// time the buffer was last updated
+    updated: f64,
+    /// time the player started or playback stalled
+    waiting_since: f64,
+    /// throughput of the last segments in mbit/s
+    throughputs: VecDeque<f64>,
+    writer: Option<MetricsWriter>,
+}
+
+impl Player {
+    /**
+     * Create a player that starts at the lowest level
+     * @param manifest     Video description from the server
+     * @param max_bitrate  Levels above this bitrate in kbit/s are never picked
+     * @param max_buffer   Playout buffer the player fills up to in s
+     * @param now          Current time
+     * @param log          Optional file the player events are logged to
+     */
+    pub fn new(
+        mut manifest: Manifest,
+        max_bitrate: Option<u64>,
+        max_buffer: f64,
+        now: f64,
+        log: Option<String>
+    ) -> Result<Self, String> {
+        if let Some(max_bitrate) = max_bitrate {
+            manifest.bitrates.retain(|bitrate| *bitrate <= max_bitrate);
+        }
+        if manifest.bitrates.is_empty() {
+            return Err(format!("No bitrate level up to {} kbit/s", max_bitrate.unwrap_or(0)));
+        }
+        if max_buffer.is_nan() || max_buffer < manifest.segment_duration * START_SEGMENTS {
+            return Err(format!("Buffer of {}s is too small for segments of {}s",
+                    max_buffer, manifest.segment_duration));
+        }
+
+        let writer = log
+            .map(|path| MetricsWriter::create(path.as_str()))
+            .transpose()?;
+
+        Ok(Self {
+            manifest,
+            max_buffer,
+            level: 0,
+            buffer: 0.0,
+            playing: false,
+            started: false,
+            position: 0.0,
+            updated: now,
+            waiting_since: now,
+            throughputs: VecDeque::with_capacity(HISTORY),
+            writer,
+        })
+    }
+
+    /**
+     * Bitrate of a level in kbit/s
+     */
+    pub fn bitrate(&self, level: usize) -> u64 {
+        self.manifest.bitrates[level]
+    }
+
+    /**
+     * Append an entry to the player log
+     */
+    fn log(&mut self, time: f64, event: PlayerEvent, duration: Option<f64>, throughput: Option<f64>)
+        -> Result<(), String> {
+        if let Some(writer) = self.writer.as_mut() {
+            writer.write(&PlayerSample {
+                time,
+                unix_time: unix_time(),
+                event,
+                bitrate: self.manifest.bitrates[self.level],
+                buffer: self.buffer,
+                duration,
+                throughput,
+            })?;
+        }
+        Ok(())
+    }
+
+    /**
+     * Play until now, stalls if the buffer runs empty
+     * @param now  Current time
+     */
+    pub fn update(&mut self, now: f64) -> Result<(), String> {
+        // the sampler and the client might race
+        let now = now.max(self.updated);
+        if self.playing {
+            let played = now - self.updated;
+            if played >= self.buffer {
+                // the stall started when the last buffered frame was played
+                let stalled = self.updated + self.buffer;
+                self.position += self.buffer;
+                self.buffer = 0.0;
+                self.playing = false;
+                self.waiting_since = stalled;
+                println!("[player] Stalled at {:.3}s", stalled);
+                self.log(stalled, PlayerEvent::Stall, None, None)?;
+            } else {
+                self.position += played;
+                self.buffer -= played;
+            }
+        }
+        self.updated = now;
+        Ok(())
+    }
+
+    /**
+     * Current state of the playback
+     */
+    pub fn state(&self) -> PlaybackState {
+        match (self.playing, self.started) {
+            (true, _) => PlaybackState::Playing,
+            (false, true) => PlaybackState::Stalled,
+            (false, false) => PlaybackState::Startup,
+        }
+    }
+
+    /**
+     * Time until there is room for another segment in the buffer
+     */
+    pub fn wait_time(&self) -> f64 {
+        if self.playing {
+            (self.buffer + self.manifest.segment_duration - self.max_buffer).max(0.0)
+        } else {
+            0.0
+        }
+    }
+
+    /**
+     * Pick the level of the next segment
+     * the highest bitrate the recent throughput supports
+     * or the lowest one if the buffer is about to run empty
+     * @param now  Current time
+     * @return level index
+     */
+    pub fn next_level(&mut self, now: f64) -> Result<usize, String> {
+        let level = if self.throughputs.is_empty() || self.buffer < self.manifest.segment_duration {
+            0
+        } else {
+            // harmonic mean, single fast segments don't count as much
+            let estimate = self.throughputs.len() as f64
+                / self.throughputs.iter().map(|throughput| 1.0 / throughput).sum::<f64>();
+            self.manifest.bitrates.iter()
+                .rposition(|bitrate| *bitrate as f64 / 1000.0 <= estimate * SAFETY_FACTOR)
+                .unwrap_or(0)
+        };
+
+        if level != self.level {
+            println!("[player] Switching from {} to {} kbit/s",
+                self.bitrate(self.level), self.bitrate(level));
+            self.level = level;
+            self.log(now, PlayerEvent::Switch, None, None)?;
+        }
+        Ok(level)
+    }
+
+    /**
+     * Add a downloaded segment to the buffer
+     * starts or resumes playback once enough is buffered
+     * @param now            Current time
+     * @param bytes          Size of the segment
+     * @param download_time  Time from request to the last byte in s
+     */
+    pub fn segment_done(&mut self, now: f64, bytes: u64, download_time: f64) -> Result<(), String> {
+        self.update(now)?;
+        self.buffer += self.manifest.segment_duration;
+
+        let throughput = bytes as f64 * 8.0 / 1_000_000.0 / download_time.max(1e-6);
+        if self.throughputs.len() == HISTORY {
+            self.throughputs.pop_front();
+        }
+        self.throughputs.push_back(throughput);
+        self.log(now, PlayerEvent::Segment, Some(download_time), Some(throughput))?;
+
+        if !self.playing && self.buffer >= self.manifest.segment_duration * START_SEGMENTS {
+            let waited = now - self.waiting_since;
+            self.playing = true;
+            if self.started {
+                println!("[player] Resumed after {:.3}s", waited);
+                self.log(now, PlayerEvent::Resume, Some(waited), None)?;
+            } else {
+                self.started = true;
+                println!("[player] Started after {:.3}s", waited);
+                self.log(now, PlayerEvent::Startup, Some(waited), None)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/**
+ * Periodically write the playback position, buffer and state
+ * Stalls are detected right away instead of with the next segment
+ * Has to be called inside a tokio runtime
+ * @param player  Player shared with the client
+ * @param config  Metrics config, nothing is written without a player log
+ * @param start   Start of the trace
+ */
+pub fn start_sampler(
+    player: Arc<Mutex<Player>>,
+    config: &MetricsConfig,
+    start: Instant
+) -> Result<(), String> {
+    let Some(path) = config.playback_file() else {
+        return Ok(());
+    };
+    let mut writer = MetricsWriter::create(path.as_str())?;
+    let interval = config.interval;
+    println!("[player] Sampling playback every {}ms to {}", interval.as_millis(), path);
+
+    tokio::spawn(async move {
+        let start = tokio::time::Instant::from_std(start);
+        let mut ticker = tokio::time::interval_at(start + interval, interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+            let time = start.elapsed().as_secs_f64();
+            let sample = {
+                let mut player = player.lock().unwrap();
+                if let Err(e) = player.update(time) {
+                    eprintln!("[player] {}", e);
+                    return;
+                }
+                PlaybackSample {
+                    time,
+                    unix_time: unix_time(),
+                    state: player.state(),
+                    position: player.position,
+                    buffer: player.buffer,
+                    bitrate: player.bitrate(player.level),
+                }
+            };
+            if let Err(e) = writer.write(&sample) {
+                eprintln!("[player] {}", e);
+                return;
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/**
+ * Summarize the quality of experience from a player log
+ * @param player_log  Player log of the run
+ * @param end         End of the run in s since the start of the trace
+ */
+pub fn summarize(player_log: &str, end: f64) -> Result<QoeSummary, String> {
+    let log: Vec<PlayerSample> = read_samples(player_log)?;
+    let events = |event: PlayerEvent| log.iter().filter(move |sample| sample.event == event);
+
+    let startup = events(PlayerEvent::Startup).next();
+    let stalls: Vec<&PlayerSample> = events(PlayerEvent::Stall).collect();
+    let resumed = events(PlayerEvent::Resume)
+        .filter_map(|sample| sample.duration)
+        .fold(0.0, |total, duration| total + duration);
+    // still stalled at the end
+    let unfinished = log.iter()
+        .rfind(|sample| matches!(sample.event, PlayerEvent::Stall | PlayerEvent::Resume))
+        .filter(|sample| sample.event == PlayerEvent::Stall)
+        .map(|sample| end - sample.time)
+        .unwrap_or(0.0);
+    let stall_time = resumed + unfinished;
+    let watched = startup.map(|startup| end - startup.time).unwrap_or(0.0);
+
+    let segments: Vec<u64> = events(PlayerEvent::Segment).map(|sample| sample.bitrate).collect();
+    let switches: Vec<f64> = log.iter()
+        .zip(log.iter().skip(1))
+        .filter(|(_, sample)| sample.event == PlayerEvent::Switch)
+        .map(|(before, switch)| (switch.bitrate as f64 - before.bitrate as f64).abs())
+        .collect();
+
+    Ok(QoeSummary {
+        startup_delay: startup.and_then(|startup| startup.duration),
+        stalls: stalls.len(),
+        stall_time,
+        stall_ratio: if watched > 0.0 { stall_time / watched } else { 0.0 },
+        played: (watched - stall_time).max(0.0),
+        segments: segments.len(),
+        mean_bitrate: segments.iter().sum::<u64>() as f64 / segments.len().max(1) as f64,
+        switches: switches.len(),
+        mean_switch: switches.iter().fold(0.0, |total, delta| total + delta)
+            / switches.len().max(1) as f64,
+    })
+}
+
+/**
+ * Summarize the player log of a finished run
+ * print it and write it as JSON next to the log
+ * @param config  Metrics config, nothing is done without a player log
+ * @param end     End of the run in s since the start of the trace
+ */
+pub fn write_summary(config: &MetricsConfig, end: f64) -> Result<(), String> {
+    let (Some(player_log), Some(path)) = (config.player_log_file.as_ref(), config.qoe_file()) else {
+        return Ok(());
+    };
+    let summary = summarize(player_log.as_str(), end)?;
+    println!("[player] Startup delay {}s, {} stalls for {:.3}s ({:.1}%), {} switches, mean bitrate {:.0} kbit/s",
+        summary.startup_delay.map(|delay| format!("{:.3}", delay)).unwrap_or(String::from("-")),
+        summary.stalls, summary.stall_time, summary.stall_ratio * 100.0,
+        summary.switches, summary.mean_bitrate);
+
+    let json = serde_json::to_string_pretty(&summary).map_err(|e| e.to_string())?;
+    std::fs::write(&path, json + "\n")
+        .map_err(|e| format!("Could not write QoE summary {}: {}", path, e))
+}
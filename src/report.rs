@@ -0,0 +1,972 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::analyze::{self, AnalyzeConfig};
+use crate::config::RecordedRun;
+use crate::metrics::{read_samples, MetricsConfig};
+use crate::player::{self, PlayerEvent};
+use crate::tcp_info::Side;
+use crate::trace::{Outage, Trace};
+use crate::udp_probe;
+use crate::Test;
+
+/**
+ * Size of the plots in px
+ */
+const PLOT_WIDTH: f64 = 800.0;
+const PLOT_HEIGHT: f64 = 300.0;
+const MARGIN_LEFT: f64 = 60.0;
+const MARGIN_RIGHT: f64 = 20.0;
+const MARGIN_TOP: f64 = 30.0;
+const MARGIN_BOTTOM: f64 = 40.0;
+
+/**
+ * Line colors of the plots
+ */
+const COLORS: [&str; 8] = [
+    "#1f77b4", "#ff7f0e", "#2ca02c", "#9467bd",
+    "#8c564b", "#e377c2", "#7f7f7f", "#17becf",
+];
+
+/**
+ * Columns of the throughput file the report uses
+ */
+#[derive(Deserialize)]
+struct ThroughputRow {
+    time: f64,
+    total_bytes: u64,
+    rate: f64,
+}
+
+/**
+ * Columns of the per flow throughput file the report uses
+ */
+#[derive(Deserialize)]
+struct FlowRow {
+    time: f64,
+    flow: usize,
+    total_bytes: u64,
+    rate: f64,
+    fairness: Option<f64>,
+}
+
+/**
+ * Columns of the tcp info files the report uses
+ */
+#[derive(Deserialize)]
+struct TcpInfoRow {
+    time: f64,
+    local_port: u16,
+    remote_port: u16,
+    rtt: f64,
+    total_retrans: u32,
+}
+
+/**
+ * Columns of the event log the report uses
+ */
+#[derive(Deserialize)]
+struct EventRow {
+    drift: f64,
+    netlink_latency: f64,
+}
+
+/**
+ * Columns of the player log the report uses
+ */
+#[derive(Deserialize)]
+struct PlayerRow {
+    time: f64,
+    event: PlayerEvent,
+    bitrate: u64,
+    duration: Option<f64>,
+}
+
+/**
+ * Columns of the playback samples the report uses
+ */
+#[derive(Deserialize)]
+struct PlaybackRow {
+    time: f64,
+    buffer: f64,
+}
+
+/**
+ * A stall of the player
+ */
+struct Stall {
+    start: f64,
+    /// None if playback never resumed
+    duration: Option<f64>,
+}
+
+/**
+ * Columns of the ping samples the report uses
+ */
+#[derive(Deserialize)]
+struct PingRow {
+    time: f64,
+    rtt: f64,
+}
+
+/**
+ * Columns of the UDP probe packet log the report uses
+ */
+#[derive(Deserialize)]
+struct PacketRow {
+    received: f64,
+    delay: f64,
+    reordered: bool,
+    duplicate: bool,
+    missing: usize,
+}
+
+/**
+ * Output format of the report
+ */
+#[derive(Clone, Copy)]
+enum Format {
+    Html,
+    Markdown,
+}
+
+/**
+ * Part of the report, rendered per format
+ */
+enum Block {
+    Heading(String),
+    Paragraph(String),
+    Table {
+        header: Vec<String>,
+        rows: Vec<Vec<String>>,
+    },
+    /// inline SVG
+    Plot(String),
+    /// TOML code
+    Code(String),
+}
+
+/**
+ * A line in a plot
+ */
+struct Series {
+    label: String,
+    /// (time in s, value)
+    points: Vec<(f64, f64)>,
+}
+
+/**
+ * Escape text for HTML/SVG
+ */
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/**
+ * Format a unix time as UTC date and time
+ * @param unix  Seconds since the unix epoch
+ */
+fn format_time(unix: f64) -> String {
+    // civil_from_days from http://howardhinnant.github.io/date_algorithms.html
+    let secs = unix.floor() as i64;
+    let days = secs.div_euclid(86_400);
+    let time = secs.rem_euclid(86_400);
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year, month, day, time / 3600, time % 3600 / 60, time % 60)
+}
+
+/**
+ * Format an optional value for a table
+ */
+fn optional(value: Option<f64>) -> String {
+    value.map(|value| format!("{:.3}", value)).unwrap_or(String::from("-"))
+}
+
+/**
+ * Tick distance of 1, 2 or 5 times a power of ten
+ * giving roughly the wanted number of ticks
+ * @param range  Range of the axis
+ * @param ticks  Wanted number of ticks
+ */
+fn tick_step(range: f64, ticks: f64) -> f64 {
+    let raw = range / ticks;
+    let magnitude = 10f64.powf(raw.log10().floor());
+    let step = match raw / magnitude {
+        normalized if normalized <= 1.0 => 1.0,
+        normalized if normalized <= 2.0 => 2.0,
+        normalized if normalized <= 5.0 => 5.0,
+        _ => 10.0,
+    };
+    step * magnitude
+}
+
+/**
+ * Format a tick label with as many decimals as the step needs
+ */
+fn tick_label(value: f64, step: f64) -> String {
+    let decimals = (-step.log10().floor()).max(0.0) as usize;
+    format!("{:.*}", decimals, value)
+}
+
+/**
+ * Draw a line plot over time as SVG
+ * outages are shaded
+ * @param title     Title above the plot
+ * @param unit      Label of the y axis
+ * @param series    Lines to draw
+ * @param outages   Outages of the trace
+ * @param duration  End of the time axis in s
+ */
+fn plot(title: &str, unit: &str, series: &[Series], outages: &[Outage], duration: f64) -> String {
+    let max = series.iter()
+        .flat_map(|series| series.points.iter().map(|(_, value)| *value))
+        .fold(0.0, f64::max);
+    let y_max = if max > 0.0 { max * 1.05 } else { 1.0 };
+    let width = PLOT_WIDTH - MARGIN_LEFT - MARGIN_RIGHT;
+    let height = PLOT_HEIGHT - MARGIN_TOP - MARGIN_BOTTOM;
+    let x = |time: f64| MARGIN_LEFT + time.clamp(0.0, duration) / duration * width;
+    let y = |value: f64| MARGIN_TOP + height - value / y_max * height;
+
+    let mut svg = String::new();
+    let _ = write!(svg, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" \
+        viewBox=\"0 0 {w} {h}\" font-family=\"sans-serif\" font-size=\"12\">",
+        w = PLOT_WIDTH, h = PLOT_HEIGHT);
+    let _ = write!(svg, "<text x=\"{}\" y=\"18\" text-anchor=\"middle\" font-size=\"14\">{}</text>",
+        PLOT_WIDTH / 2.0, escape(title));
+
+    for outage in outages {
+        let (start, end) = (x(outage.start), x(outage.end));
+        if end > start {
+            let _ = write!(svg, "<rect x=\"{:.1}\" y=\"{}\" width=\"{:.1}\" height=\"{}\" \
+                fill=\"#d62728\" fill-opacity=\"0.15\"/>", start, MARGIN_TOP, end - start, height);
+        }
+    }
+
+    // grid with labels
+    let step = tick_step(duration, 10.0);
+    let mut tick = 0.0;
+    while tick <= duration + step / 1000.0 {
+        let _ = write!(svg, "<line x1=\"{x:.1}\" y1=\"{}\" x2=\"{x:.1}\" y2=\"{}\" stroke=\"#ddd\"/>\
+            <text x=\"{x:.1}\" y=\"{}\" text-anchor=\"middle\">{}</text>",
+            MARGIN_TOP, MARGIN_TOP + height, MARGIN_TOP + height + 15.0,
+            tick_label(tick, step), x = x(tick));
+        tick += step;
+    }
+    let step = tick_step(y_max, 5.0);
+    let mut tick = 0.0;
+    while tick <= y_max {
+        let _ = write!(svg, "<line x1=\"{}\" y1=\"{y:.1}\" x2=\"{}\" y2=\"{y:.1}\" stroke=\"#ddd\"/>\
+            <text x=\"{}\" y=\"{:.1}\" text-anchor=\"end\">{}</text>",
+            MARGIN_LEFT, MARGIN_LEFT + width, MARGIN_LEFT - 5.0, y(tick) + 4.0,
+            tick_label(tick, step), y = y(tick));
+        tick += step;
+    }
+    let _ = write!(svg, "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" stroke=\"#000\"/>",
+        MARGIN_LEFT, MARGIN_TOP, width, height);
+    let _ = write!(svg, "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">time [s]</text>",
+        MARGIN_LEFT + width / 2.0, PLOT_HEIGHT - 5.0);
+    let _ = write!(svg, "<text transform=\"translate(14 {}) rotate(-90)\" text-anchor=\"middle\">{}</text>",
+        MARGIN_TOP + height / 2.0, escape(unit));
+
+    for (idx, series) in series.iter().enumerate() {
+        let color = COLORS[idx % COLORS.len()];
+        let points: Vec<String> = series.points.iter()
+            .map(|(time, value)| format!("{:.1},{:.1}", x(*time), y(*value)))
+            .collect();
+        let _ = write!(svg, "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"1.5\"/>",
+            points.join(" "), color);
+
+        // legend in the upper right corner
+        let legend_y = MARGIN_TOP + 15.0 + idx as f64 * 15.0;
+        let legend_x = MARGIN_LEFT + width - 150.0;
+        let _ = write!(svg, "<line x1=\"{}\" y1=\"{ly}\" x2=\"{}\" y2=\"{ly}\" stroke=\"{}\" \
+            stroke-width=\"2\"/><text x=\"{}\" y=\"{}\">{}</text>",
+            legend_x, legend_x + 20.0, color, legend_x + 25.0, legend_y + 4.0,
+            escape(&series.label), ly = legend_y);
+    }
+
+    svg.push_str("</svg>");
+    svg
+}
+
+/**
+ * Render the report
+ * @param title   Title of the document
+ * @param blocks  Content
+ * @param format  HTML or Markdown
+ */
+fn render(title: &str, blocks: &[Block], format: Format) -> String {
+    let mut out = String::new();
+    match format {
+        Format::Html => {
+            let _ = write!(out, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+                <title>{}</title>\n<style>\n\
+                body {{ font-family: sans-serif; max-width: 860px; margin: 2em auto; }}\n\
+                table {{ border-collapse: collapse; margin: 1em 0; }}\n\
+                th, td {{ border: 1px solid #ccc; padding: 2px 8px; text-align: right; }}\n\
+                pre {{ background: #f4f4f4; padding: 8px; }}\n\
+                </style>\n</head>\n<body>\n<h1>{}</h1>\n", escape(title), escape(title));
+        },
+        Format::Markdown => {
+            let _ = write!(out, "# {}\n\n", title);
+        },
+    }
+
+    for block in blocks {
+        match (block, format) {
+            (Block::Heading(text), Format::Html) => {
+                let _ = writeln!(out, "<h2>{}</h2>", escape(text));
+            },
+            (Block::Heading(text), Format::Markdown) => {
+                let _ = write!(out, "## {}\n\n", text);
+            },
+            (Block::Paragraph(text), Format::Html) => {
+                let _ = writeln!(out, "<p>{}</p>", escape(text));
+            },
+            (Block::Paragraph(text), Format::Markdown) => {
+                let _ = write!(out, "{}\n\n", text);
+            },
+            (Block::Table { header, rows }, Format::Html) => {
+                out.push_str("<table>\n<tr>");
+                for cell in header {
+                    let _ = write!(out, "<th>{}</th>", escape(cell));
+                }
+                out.push_str("</tr>\n");
+                for row in rows {
+                    out.push_str("<tr>");
+                    for cell in row {
+                        let _ = write!(out, "<td>{}</td>", escape(cell));
+                    }
+                    out.push_str("</tr>\n");
+                }
+                out.push_str("</table>\n");
+            },
+            (Block::Table { header, rows }, Format::Markdown) => {
+                let _ = writeln!(out, "| {} |", header.join(" | "));
+                let _ = writeln!(out, "|{}", "---|".repeat(header.len()));
+                for row in rows {
+                    let _ = writeln!(out, "| {} |", row.join(" | "));
+                }
+                out.push('\n');
+            },
+            // SVG is valid inline HTML in Markdown too
+            (Block::Plot(svg), Format::Html) => {
+                let _ = writeln!(out, "<div>{}</div>", svg);
+            },
+            (Block::Plot(svg), Format::Markdown) => {
+                let _ = write!(out, "{}\n\n", svg);
+            },
+            (Block::Code(code), Format::Html) => {
+                let _ = writeln!(out, "<pre>{}</pre>", escape(code));
+            },
+            (Block::Code(code), Format::Markdown) => {
+                let _ = write!(out, "```toml\n{}```\n\n", code);
+            },
+        }
+    }
+
+    if let Format::Html = format {
+        out.push_str("</body>\n</html>\n");
+    }
+    out
+}
+
+/**
+ * Read a metrics file of the run if it was recorded
+ * missing files are skipped with a warning
+ * @param path  Path to the file, None if it wasn't recorded
+ */
+fn optional_samples<T: serde::de::DeserializeOwned>(
+    path: Option<String>
+) -> Result<Option<Vec<T>>, String> {
+    match path {
+        Some(path) if Path::new(&path).exists() => read_samples(path.as_str()).map(Some),
+        Some(path) => {
+            eprintln!("[report] {} is missing, skipping it", path);
+            Ok(None)
+        },
+        None => Ok(None),
+    }
+}
+
+/**
+ * Name of a workload with its number of flows if there are several
+ */
+fn with_flows(name: &str, flows: Option<usize>) -> String {
+    match flows {
+        Some(flows) if flows > 1 => format!("{} with {} flows", name, flows),
+        _ => String::from(name),
+    }
+}
+
+/**
+ * Whether a time falls into an outage
+ */
+fn in_outage(outages: &[Outage], time: f64) -> bool {
+    outages.iter().any(|outage| time >= outage.start && time < outage.end)
+}
+
+/**
+ * Overview table of the run
+ * @param run      Recorded run
+ * @param outages  Outages of the trace
+ */
+fn overview_blocks(run: &RecordedRun, outages: &[Outage]) -> Vec<Block> {
+    let workload = match &run.experiment.workload {
+        Test::Download { flows } => with_flows("download", *flows),
+        Test::Upload { flows } => with_flows("upload", *flows),
+        Test::Bidirectional => String::from("download and upload"),
+        Test::Stream { max_bitrate, .. } => match max_bitrate {
+            Some(max_bitrate) => format!("stream up to {} kbit/s", max_bitrate),
+            None => String::from("stream"),
+        },
+        Test::Udp { rate, size } => format!("udp probe at {} mbit/s with {} byte packets",
+            rate.unwrap_or(udp_probe::DEFAULT_RATE), size.unwrap_or(udp_probe::DEFAULT_SIZE)),
+        Test::Host { interface } => format!("host on {}", interface),
+    };
+    let outage_time = outages.iter()
+        .fold(0.0, |total, outage| total + outage.end - outage.start);
+    vec![Block::Table {
+        header: vec![String::from("run"), String::new()],
+        rows: vec![
+            vec![String::from("started"), format_time(run.started)],
+            vec![String::from("version"), run.version.clone()],
+            vec![String::from("workload"), workload],
+            vec![String::from("congestion control"), run.congestion_control.clone()],
+            vec![String::from("trace"), run.experiment.trace.file.clone()],
+            vec![String::from("outages"),
+                format!("{} ({:.1} s total)", outages.len(), outage_time)],
+        ],
+    }]
+}
+
+/**
+ * Throughput section, one line per direction
+ * @param throughput  Samples with their label
+ * @param outages     Outages of the trace
+ * @param duration    Length of the run in s
+ */
+fn throughput_blocks(
+    throughput: &[(String, Vec<ThroughputRow>)],
+    outages: &[Outage],
+    duration: f64
+) -> Vec<Block> {
+    if throughput.is_empty() {
+        return Vec::new();
+    }
+    let mean = |rows: Vec<&ThroughputRow>| match rows.len() {
+        0 => None,
+        n => Some(rows.iter().map(|row| row.rate).sum::<f64>() / n as f64),
+    };
+    let series: Vec<Series> = throughput.iter().map(|(label, rows)| Series {
+        label: label.clone(),
+        points: rows.iter().map(|row| (row.time, row.rate)).collect(),
+    }).collect();
+    vec![
+        Block::Heading(String::from("Throughput")),
+        Block::Table {
+            header: ["", "transferred [MB]", "mean [mbit/s]", "outside of outages [mbit/s]"]
+                .map(String::from).to_vec(),
+            rows: throughput.iter().map(|(label, rows)| vec![
+                label.clone(),
+                format!("{:.1}", rows.last().map(|row| row.total_bytes).unwrap_or(0) as f64
+                    / 1_000_000.0),
+                optional(mean(rows.iter().collect())),
+                optional(mean(rows.iter()
+                    .filter(|row| !in_outage(outages, row.time))
+                    .collect())),
+            ]).collect(),
+        },
+        Block::Plot(plot("Throughput", "mbit/s", &series, outages, duration)),
+    ]
+}
+
+/**
+ * Throughput per flow and fairness between them
+ * @param flows     Per flow samples
+ * @param outages   Outages of the trace
+ * @param duration  Length of the run in s
+ */
+fn flow_blocks(flows: &[FlowRow], outages: &[Outage], duration: f64) -> Vec<Block> {
+    if flows.is_empty() {
+        return Vec::new();
+    }
+    let mut per_flow: BTreeMap<usize, Vec<&FlowRow>> = BTreeMap::new();
+    for row in flows {
+        per_flow.entry(row.flow).or_default().push(row);
+    }
+    let mean = |values: Vec<f64>| match values.len() {
+        0 => None,
+        n => Some(values.iter().sum::<f64>() / n as f64),
+    };
+    let series: Vec<Series> = per_flow.iter().map(|(flow, rows)| Series {
+        label: format!("flow {}", flow),
+        points: rows.iter().map(|row| (row.time, row.rate)).collect(),
+    }).collect();
+
+    // every flow of an interval has the same index, take it from the first
+    let fairness: Vec<(f64, f64)> = per_flow.values().next().into_iter().flatten()
+        .filter_map(|row| row.fairness.map(|fairness| (row.time, fairness)))
+        .collect();
+    let fairness_text = format!(
+        "Jain's fairness index between the {} flows is {} on average, \
+        {} outside of outages, intervals without traffic are left out.",
+        per_flow.len(),
+        optional(mean(fairness.iter().map(|(_, fairness)| *fairness).collect())),
+        optional(mean(fairness.iter()
+            .filter(|(time, _)| !in_outage(outages, *time))
+            .map(|(_, fairness)| *fairness)
+            .collect())));
+
+    vec![
+        Block::Heading(String::from("Flows")),
+        Block::Table {
+            header: ["flow", "transferred [MB]", "mean [mbit/s]", "outside of outages [mbit/s]"]
+                .map(String::from).to_vec(),
+            rows: per_flow.iter().map(|(flow, rows)| vec![
+                flow.to_string(),
+                format!("{:.1}", rows.last().map(|row| row.total_bytes).unwrap_or(0) as f64
+                    / 1_000_000.0),
+                optional(mean(rows.iter().map(|row| row.rate).collect())),
+                optional(mean(rows.iter()
+                    .filter(|row| !in_outage(outages, row.time))
+                    .map(|row| row.rate)
+                    .collect())),
+            ]).collect(),
+        },
+        Block::Plot(plot("Throughput per flow", "mbit/s", &series, outages, duration)),
+        Block::Paragraph(fairness_text),
+        Block::Plot(plot("Jain's fairness index", "index", &[Series {
+            label: String::from("fairness"),
+            points: fairness,
+        }], outages, duration)),
+    ]
+}
+
+/**
+ * RTT of the pings and per connection
+ * @param tcp_info  Tcp info samples per side
+ * @param pings     Ping samples, None if not recorded
+ * @param outages   Outages of the trace
+ * @param duration  Length of the run in s
+ */
+fn rtt_blocks(
+    tcp_info: &[(Side, Vec<TcpInfoRow>)],
+    pings: Option<&[PingRow]>,
+    outages: &[Outage],
+    duration: f64
+) -> Vec<Block> {
+    let mut blocks: Vec<Block> = Vec::new();
+    if !tcp_info.is_empty() || pings.is_some() {
+        blocks.push(Block::Heading(String::from("RTT")));
+    }
+    if let Some(pings) = pings.filter(|pings| !pings.is_empty()) {
+        let rtts = |rows: Vec<&PingRow>| match rows.len() {
+            0 => (None, None),
+            n => (Some(rows.iter().map(|row| row.rtt).sum::<f64>() / n as f64),
+                Some(rows.iter().map(|row| row.rtt).fold(0.0, f64::max))),
+        };
+        let (mean, max) = rtts(pings.iter().collect());
+        let (mean_outside, _) = rtts(pings.iter()
+            .filter(|row| !in_outage(outages, row.time))
+            .collect());
+        blocks.push(Block::Paragraph(format!(
+            "{} pings answered, RTT mean {} ms and max {} ms, mean {} ms outside of outages.",
+            pings.len(), optional(mean), optional(max), optional(mean_outside))));
+        blocks.push(Block::Plot(plot("Ping RTT from ns2 to ns1", "ms", &[Series {
+            label: String::from("ping"),
+            points: pings.iter().map(|row| (row.time, row.rtt)).collect(),
+        }], outages, duration)));
+    }
+    if !tcp_info.is_empty() {
+        let mut series: Vec<Series> = Vec::new();
+        let mut rows: Vec<Vec<String>> = Vec::new();
+        for (side, samples) in tcp_info {
+            let mut connections: BTreeMap<(u16, u16), Vec<&TcpInfoRow>> = BTreeMap::new();
+            for sample in samples {
+                connections.entry((sample.local_port, sample.remote_port))
+                    .or_default()
+                    .push(sample);
+            }
+            for ((local_port, remote_port), samples) in connections {
+                let label = format!("{} :{} to :{}", side, local_port, remote_port);
+                rows.push(vec![
+                    label.clone(),
+                    format!("{:.1}", samples.iter().map(|sample| sample.rtt).sum::<f64>()
+                        / samples.len() as f64),
+                    format!("{:.1}", samples.iter().map(|sample| sample.rtt).fold(0.0, f64::max)),
+                    samples.iter().map(|sample| sample.total_retrans).max()
+                        .unwrap_or(0).to_string(),
+                ]);
+                series.push(Series {
+                    label,
+                    points: samples.iter().map(|sample| (sample.time, sample.rtt)).collect(),
+                });
+            }
+        }
+        blocks.push(Block::Plot(plot("Smoothed RTT", "ms", &series, outages, duration)));
+        blocks.push(Block::Table {
+            header: ["connection", "mean RTT [ms]", "max RTT [ms]", "retransmissions"]
+                .map(String::from).to_vec(),
+            rows,
+        });
+    }
+    blocks
+}
+
+/**
+ * Loss and one-way delay of the UDP probe
+ * @param packets   Packet log of the receiver
+ * @param outages   Outages of the trace
+ * @param duration  Length of the run in s
+ */
+fn udp_blocks(packets: &[PacketRow], outages: &[Outage], duration: f64) -> Vec<Block> {
+    if packets.is_empty() {
+        return Vec::new();
+    }
+    let lost = packets.last().map(|row| row.missing).unwrap_or(0);
+
+    // highest delay per 100ms, the plot would get huge with every packet
+    let mut bins: BTreeMap<u64, f64> = BTreeMap::new();
+    for row in packets {
+        let bin = bins.entry((row.received * 10.0) as u64).or_insert(0.0);
+        *bin = bin.max(row.delay);
+    }
+
+    vec![
+        Block::Heading(String::from("UDP probe")),
+        Block::Paragraph(format!(
+            "{} packets received, {} lost ({:.2} %), {} reordered, {} duplicates, \
+            one-way delay mean {:.1} ms and max {:.1} ms.",
+            packets.len(), lost, lost as f64 / (packets.len() + lost) as f64 * 100.0,
+            packets.iter().filter(|row| row.reordered).count(),
+            packets.iter().filter(|row| row.duplicate).count(),
+            packets.iter().map(|row| row.delay).sum::<f64>() / packets.len() as f64,
+            packets.iter().map(|row| row.delay).fold(0.0, f64::max))),
+        Block::Plot(plot("One-way delay", "ms", &[Series {
+            label: String::from("max per 100ms"),
+            points: bins.into_iter().map(|(bin, delay)| (bin as f64 / 10.0, delay)).collect(),
+        }], outages, duration)),
+    ]
+}
+
+/**
+ * Bitrate and stalls of the stream player
+ * @param player    Player log
+ * @param playback  Playout buffer samples, None if not recorded
+ * @param metrics   Metrics files of the run for the QoE summary
+ * @param outages   Outages of the trace
+ * @param duration  Length of the run in s
+ */
+fn stream_blocks(
+    player: &[PlayerRow],
+    playback: Option<&[PlaybackRow]>,
+    metrics: &MetricsConfig,
+    outages: &[Outage],
+    duration: f64
+) -> Result<Vec<Block>, String> {
+    if player.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut stalls: Vec<Stall> = Vec::new();
+    for row in player {
+        match row.event {
+            PlayerEvent::Stall => stalls.push(Stall { start: row.time, duration: None }),
+            PlayerEvent::Resume => if let Some(stall) = stalls.last_mut() {
+                stall.duration = row.duration;
+            },
+            _ => (),
+        }
+    }
+    let segments: Vec<&PlayerRow> = player.iter()
+        .filter(|row| row.event == PlayerEvent::Segment)
+        .collect();
+    let switches: Vec<&PlayerRow> = player.iter()
+        .filter(|row| row.event == PlayerEvent::Switch)
+        .collect();
+    // summary of the run, made up from the log if the run didn't write one
+    let summary = match metrics.qoe_file().filter(|file| Path::new(file).exists()) {
+        Some(file) => std::fs::read_to_string(&file)
+            .map_err(|e| e.to_string())
+            .and_then(|json| serde_json::from_str(&json).map_err(|e| e.to_string()))
+            .map_err(|e| format!("Could not read QoE summary {}: {}", file, e))?,
+        None => player::summarize(metrics.player_log_file.as_deref().unwrap_or_default(),
+            duration)?,
+    };
+
+    let mut blocks = vec![
+        Block::Heading(String::from("Stream")),
+        Block::Table {
+            header: vec![String::from("QoE"), String::new()],
+            rows: vec![
+                vec![String::from("startup delay [s]"), optional(summary.startup_delay)],
+                vec![String::from("stalls"), summary.stalls.to_string()],
+                vec![String::from("stall time [s]"), format!("{:.3}", summary.stall_time)],
+                vec![String::from("stall ratio [%]"), format!("{:.2}", summary.stall_ratio * 100.0)],
+                vec![String::from("played [s]"), format!("{:.1}", summary.played)],
+                vec![String::from("segments"), summary.segments.to_string()],
+                vec![String::from("mean bitrate [kbit/s]"), format!("{:.0}", summary.mean_bitrate)],
+                vec![String::from("bitrate switches"), summary.switches.to_string()],
+                vec![String::from("mean switch [kbit/s]"), format!("{:.0}", summary.mean_switch)],
+            ],
+        },
+    ];
+    if let Some(playback) = playback {
+        blocks.push(Block::Plot(plot("Playout buffer", "s", &[Series {
+            label: String::from("buffer"),
+            points: playback.iter().map(|row| (row.time, row.buffer)).collect(),
+        }], outages, duration)));
+    }
+    blocks.push(Block::Plot(plot("Segment bitrate", "kbit/s", &[Series {
+        label: String::from("bitrate"),
+        points: segments.iter().map(|row| (row.time, row.bitrate as f64)).collect(),
+    }], outages, duration)));
+
+    // everything from the start of an outage until the next one
+    if !outages.is_empty() {
+        let window = |idx: usize| (outages[idx].start,
+            outages.get(idx + 1).map(|outage| outage.start).unwrap_or(f64::INFINITY));
+        blocks.push(Block::Table {
+            header: ["#", "start [s]", "end [s]", "stalls", "stall time [s]",
+                "switches", "lowest bitrate [kbit/s]"].map(String::from).to_vec(),
+            rows: outages.iter().enumerate().map(|(idx, outage)| {
+                let (from, to) = window(idx);
+                let stalls: Vec<&Stall> = stalls.iter()
+                    .filter(|stall| stall.start >= from && stall.start < to)
+                    .collect();
+                vec![
+                    (idx + 1).to_string(),
+                    format!("{:.3}", outage.start),
+                    format!("{:.3}", outage.end),
+                    stalls.len().to_string(),
+                    format!("{:.3}", stalls.iter().filter_map(|stall| stall.duration)
+                        .fold(0.0, |total, duration| total + duration)),
+                    switches.iter().filter(|row| row.time >= from && row.time < to)
+                        .count().to_string(),
+                    segments.iter().filter(|row| row.time >= from && row.time < to)
+                        .map(|row| row.bitrate).min()
+                        .map(|bitrate| bitrate.to_string()).unwrap_or(String::from("-")),
+                ]
+            }).collect(),
+        });
+    }
+    Ok(blocks)
+}
+
+/**
+ * Outages, with the capture analysis if there is a capture
+ * @param capture  Capture file of the run, if any
+ * @param outages  Outages of the trace
+ * @param port     Server port of the connections in the capture
+ */
+fn outage_blocks(capture: Option<&str>, outages: &[Outage], port: u16) -> Vec<Block> {
+    let analysis = capture
+        .filter(|file| Path::new(file).exists())
+        .and_then(|file| {
+            let config = AnalyzeConfig { port, ..AnalyzeConfig::default() };
+            analyze::analyze_capture(file, outages, &config)
+                .map_err(|e| eprintln!("[report] Skipping capture analysis: {}", e))
+                .ok()
+        });
+    let mut blocks = vec![Block::Heading(String::from("Outages"))];
+    match analysis {
+        Some(analysis) => {
+            blocks.push(Block::Paragraph(format!(
+                "Capture analysis of {} connection(s) on port {} captured on {}. \
+                Retransmissions, duplicate ACKs and RTOs are counted until the next outage, \
+                first delivery and recovery times are from the end of the outage.",
+                analysis.connections, port, analysis.interface)));
+            blocks.push(Block::Table {
+                header: ["#", "start [s]", "end [s]", "direction", "retransmissions",
+                    "dup ACKs", "RTOs", "first delivery [s]", "pre-outage [mbit/s]",
+                    "recovery [s]"].map(String::from).to_vec(),
+                rows: analysis.outages.iter().map(|report| vec![
+                    report.outage.to_string(),
+                    format!("{:.3}", report.start),
+                    format!("{:.3}", report.end),
+                    String::from(report.direction),
+                    report.retransmissions.to_string(),
+                    report.dup_acks.to_string(),
+                    report.rto_events.to_string(),
+                    optional(report.first_delivery),
+                    optional(report.pre_outage_rate),
+                    optional(report.recovery_time),
+                ]).collect(),
+            });
+        },
+        None => blocks.push(Block::Table {
+            header: ["#", "start [s]", "end [s]", "duration [s]", "direction"]
+                .map(String::from).to_vec(),
+            rows: outages.iter().enumerate().map(|(idx, outage)| vec![
+                (idx + 1).to_string(),
+                format!("{:.3}", outage.start),
+                format!("{:.3}", outage.end),
+                format!("{:.3}", outage.end - outage.start),
+                String::from(outage.directions()),
+            ]).collect(),
+        }),
+    }
+    blocks
+}
+
+/**
+ * How well the trace was played
+ * @param events  Event log of the trace playback
+ */
+fn playback_blocks(events: &[EventRow]) -> Vec<Block> {
+    if events.is_empty() {
+        return Vec::new();
+    }
+    vec![
+        Block::Heading(String::from("Trace playback")),
+        Block::Paragraph(format!(
+            "{} events applied, drift up to {:.3} ms, mean netlink latency {:.3} ms.",
+            events.len(),
+            events.iter().map(|event| event.drift.abs()).fold(0.0, f64::max),
+            events.iter().map(|event| event.netlink_latency).sum::<f64>()
+                / events.len() as f64)),
+    ]
+}
+
+/**
+ * Generate a self-contained report of a run
+ * with plots of throughput and RTT and results per outage
+ * The run is found through its metadata file which has the paths
+ * of the trace, the metrics files and the capture
+ * @param metadata_file  Metadata file of the run
+ * @param output         Report file, Markdown for .md files, HTML otherwise
+ * @param port           Server port of the connections in the capture
+ */
+pub fn report(metadata_file: &str, output: &str, port: u16) -> Result<(), String> {
+    let run = RecordedRun::from_file(metadata_file)?;
+    let experiment = &run.experiment;
+    let metrics = experiment.metrics();
+
+    // trace for the outages
+    let open = |path: &str| csv::Reader::from_path(path)
+        .map_err(|e| format!("Could not open trace {}: {}", path, e));
+    let mut rdr = open(experiment.trace.file.as_str())?;
+    let trace = match &experiment.trace.downlink_file {
+        Some(downlink_file) => Trace::new_asymmetric(&mut rdr, &mut open(downlink_file)?, None),
+        None => Trace::new(&mut rdr, None),
+    }?;
+    let outages = trace.outages();
+
+    // one file per direction for the bidirectional test
+    let throughput_files = match &experiment.workload {
+        Test::Bidirectional => ["download", "upload"].into_iter()
+            .map(|direction| (String::from(direction), metrics.direction_throughput_file(direction)))
+            .collect(),
+        _ => vec![(String::from("throughput"), metrics.throughput_file.clone())],
+    };
+    let throughput: Vec<(String, Vec<ThroughputRow>)> = throughput_files.into_iter()
+        .map(|(label, file)| Ok(optional_samples(file)?.map(|rows| (label, rows))))
+        .collect::<Result<Vec<Option<(String, Vec<ThroughputRow>)>>, String>>()?
+        .into_iter()
+        .flatten()
+        .collect();
+    // per flow samples are only written with several flows
+    let flows: Option<Vec<FlowRow>> = match &experiment.workload {
+        Test::Download { flows: Some(flows) } | Test::Upload { flows: Some(flows) }
+            if *flows > 1 => optional_samples(metrics.flows_file())?,
+        _ => None,
+    };
+    let tcp_info: Vec<(Side, Vec<TcpInfoRow>)> = [Side::Client, Side::Server].into_iter()
+        .map(|side| Ok(optional_samples(metrics.tcp_info_file(side))?.map(|rows| (side, rows))))
+        .collect::<Result<Vec<Option<(Side, Vec<TcpInfoRow>)>>, String>>()?
+        .into_iter()
+        .flatten()
+        .collect();
+    let events: Option<Vec<EventRow>> = optional_samples(metrics.event_log_file.clone())?;
+    let packets: Option<Vec<PacketRow>> = optional_samples(metrics.packet_log_file.clone())?;
+    let pings: Option<Vec<PingRow>> = optional_samples(metrics.ping_file.clone())?;
+    let player: Option<Vec<PlayerRow>> = optional_samples(metrics.player_log_file.clone())?;
+    let playback: Option<Vec<PlaybackRow>> = optional_samples(metrics.playback_file())?;
+
+    let duration = throughput.iter().flat_map(|(_, rows)| rows.iter().map(|row| row.time))
+        .chain(packets.iter().flatten().map(|row| row.received))
+        .chain(pings.iter().flatten().map(|row| row.time))
+        .chain(flows.iter().flatten().map(|row| row.time))
+        .chain(player.iter().flatten().map(|row| row.time))
+        .chain(tcp_info.iter().flat_map(|(_, rows)| rows.iter().map(|row| row.time)))
+        .chain(outages.iter().map(|outage| outage.end))
+        .fold(0.0, f64::max);
+    let duration = if duration > 0.0 { duration } else { 1.0 };
+
+    let mut blocks: Vec<Block> = overview_blocks(&run, &outages);
+    blocks.extend(throughput_blocks(&throughput, &outages, duration));
+    blocks.extend(flow_blocks(flows.as_deref().unwrap_or_default(), &outages, duration));
+    blocks.extend(rtt_blocks(&tcp_info, pings.as_deref(), &outages, duration));
+    blocks.extend(udp_blocks(packets.as_deref().unwrap_or_default(), &outages, duration));
+    blocks.extend(stream_blocks(player.as_deref().unwrap_or_default(), playback.as_deref(),
+        &metrics, &outages, duration)?);
+    blocks.extend(outage_blocks(experiment.capture.file.as_deref(), &outages, port));
+    blocks.extend(playback_blocks(events.as_deref().unwrap_or_default()));
+
+    blocks.push(Block::Heading(String::from("Settings")));
+    blocks.push(Block::Code(toml::to_string_pretty(experiment).map_err(|e| e.to_string())?));
+
+    let format = if output.ends_with(".md") || output.ends_with(".markdown") {
+        Format::Markdown
+    } else {
+        Format::Html
+    };
+    let title = format!("Run report {}", format_time(run.started));
+    std::fs::write(output, render(&title, &blocks, format))
+        .map_err(|e| format!("Could not write report {}: {}", output, e))?;
+    println!("[report] Wrote {}", output);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn date_conversion() {
+        assert_eq!(format_time(0.0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_time(1.9), "1970-01-01 00:00:01 UTC");
+        assert_eq!(format_time(1_709_210_096.0), "2024-02-29 12:34:56 UTC");
+        // leap year as it is divisible by 400, 1900 is not one
+        assert_eq!(format_time(951_782_400.0), "2000-02-29 00:00:00 UTC");
+        assert_eq!(format_time(-2_203_891_200.0), "1900-03-01 00:00:00 UTC");
+        assert_eq!(format_time(1_677_628_800.0 - 1.0), "2023-02-28 23:59:59 UTC");
+
+        // before the epoch
+        assert_eq!(format_time(-1.0), "1969-12-31 23:59:59 UTC");
+        assert_eq!(format_time(-0.5), "1969-12-31 23:59:59 UTC");
+        assert_eq!(format_time(-11_670_976_800.0), "1600-02-29 06:00:00 UTC");
+    }
+
+    #[test]
+    fn tick_steps() {
+        assert_eq!(tick_step(10.0, 10.0), 1.0);
+        assert_eq!(tick_step(100.0, 10.0), 10.0);
+        assert_eq!(tick_step(15.0, 10.0), 2.0);
+        assert_eq!(tick_step(30.0, 10.0), 5.0);
+        assert_eq!(tick_step(7.0, 10.0), 1.0);
+        assert_eq!(tick_step(60.0, 5.0), 20.0);
+        assert_eq!(tick_step(0.25, 5.0), 0.05);
+        assert_eq!(tick_step(2.5, 10.0), 0.5);
+
+        assert_eq!(tick_label(20.0, 20.0), "20");
+        assert_eq!(tick_label(0.5, 0.5), "0.5");
+        assert_eq!(tick_label(0.1, 0.05), "0.10");
+    }
+}
@@ -1,11 +1,15 @@
 use rtnetlink::Handle;
-use netlink_packet_utils::{byteorder::{ByteOrder, NativeEndian}, nla::DefaultNla, traits::Emitable};
-use netlink_packet_route::{link::LinkMessageBuffer, tc::{TcAttribute, TcOption}};
-use futures::TryStreamExt;
+use netlink_packet_utils::nla::{DefaultNla, Nla, NlasIterator};
+use netlink_packet_utils::traits::Emitable;
+use netlink_packet_core::{NetlinkMessage, NetlinkPayload, NLM_F_ACK, NLM_F_CREATE, NLM_F_DUMP, NLM_F_EXCL, NLM_F_REPLACE, NLM_F_REQUEST};
+use netlink_packet_route::{link::LinkMessageBuffer, tc::{TcAttribute, TcMessage}, RouteNetlinkMessage};
+use futures::{StreamExt, TryStreamExt};
 use std::io::{self, BufRead};
 use std::fs::File;
 use std::str::FromStr;
 
+use crate::netem::NetemConfig;
+
 /**
  * Get the internal id of an interface by its name
  * A working handle must be created in a tokio runtime like:
@@ -55,55 +59,22 @@ pub async fn get_interface_id_by_name(handle: Handle, name: String) -> Result<u3
 }
 
 
-/**
- * consts from /include/uapi/linux/pkt_sched.h
- * in the linux source tree
- * these map to the "kind" of nla message
- */
-
-const TCA_NETEM_UNSPEC: u16 = 0;
-const TCA_NETEM_CORR: u16 = 1;
-const TCA_NETEM_DELAY_DIST: u16 = 2;
-const TCA_NETEM_REORDER: u16 = 3;
-const TCA_NETEM_CORRUPT: u16 = 4;
-// const TCA_NETEM_LOSS: u16 = 5;
-const TCA_NETEM_RATE: u16 = 6;
-// const TCA_NETEM_ECN: u16 = 7;
-const TCA_NETEM_RATE64: u16 = 8;
-// const TCA_NETEM_PAD: u16 = 9;
-const TCA_NETEM_LATENCY64: u16 = 10;
-const TCA_NETEM_JITTER64: u16 = 11;
-const TCA_NETEM_SLOT: u16 = 12;
-// const TCA_NETEM_SLOT_DIST: u16 = 13;
-// const TCA_NETEM_PRNG_SEED: u16 = 14;
-
 /**
  * Common netem qdisc handler
  * @param handle        Handle for rtnetlink
  * @param interface_id  ID of the interface
  * @param inplace       Change qdiscc inplace
  *                      this requires handle to be the same as existing
- * @param limit         Limit for packets in queue
- * @param loss          Loss rate in percent (0-100)
- * @param rate          Rate limit in byte/s
- * @param latency       Added delay in ns
- * @param jitter        Jitter for delay in ns
+ * @param config        Netem configuration
  */
 pub async fn qdisc_netem(
     handle: Handle,
     interface_id: u32,
     inplace: bool,
-    limit: u32,
-    loss: u32,
-    rate: u64,
-    latency: i64,
-    jitter: i64,
-    distribution: Vec<i16>
+    config: &NetemConfig
 ) -> Result<(), String> {
     // argument constraints
-    if loss > 100 {
-        return Err(format!("Loss value {} not in range [0..100]", loss));
-    }
+    config.validate()?;
 
     let mut request = if inplace {
         handle
@@ -122,170 +93,455 @@ pub async fn qdisc_netem(
     request.message_mut().attributes.push(
         TcAttribute::Kind(String::from("netem")));
 
-    // add options
-    let mut options: Vec<TcOption> = Vec::new();
-
-    // these emulate the structs from
-    // /include/uapi/linux/pkt_sched.h
-    // may be x86 exclusive because of this but whatever
-    //
-    // types of standalone attributes are from
-    // /net/sched/sch_netem.c
-
-    // tc_netem_qopt
-    let kind = TCA_NETEM_UNSPEC;
-    let mut value = vec![0; 20];
-    //__u32	latency;	/* added delay (us) */
-    // 1st in tc_netem_qopts struct but not parsed?
-    //__u32   limit;		/* fifo limit (packets) */
-    NativeEndian::write_u32(&mut value[0..4], limit as u32);
-    //__u32	loss;		/* random packet loss (0=none ~0=100%) */
-    NativeEndian::write_u32(&mut value[4..8],
-        (u32::MAX as f32 * (loss as f32 / 100.0)) as u32);
-    //__u32	gap;		/* re-ordering gap (0 for none) */
-    NativeEndian::write_u32(&mut value[8..12], 0 as u32);
-    //__u32   duplicate;	/* random packet dup  (0=none ~0=100%) */
-    NativeEndian::write_u32(&mut value[12..16], 0 as u32);
-    //__u32	jitter;		/* random jitter in latency (us) */
-    // skewed by like << 6 bitshift or sth idk
-    NativeEndian::write_u32(&mut value[16..20], 0 as u32);
-    options.push(TcOption::Other(DefaultNla::new(kind, value)));
-
-    // tc_netem_corr
-    let kind = TCA_NETEM_CORR;
-    let mut value = vec![0; 12];
-    //__u32	delay_corr;	/* delay correlation */
-    NativeEndian::write_u32(&mut value[0..4], 0 as u32);
-    //__u32	loss_corr;	/* packet loss correlation */
-    NativeEndian::write_u32(&mut value[4..8], 0 as u32);
-    //__u32	dup_corr;	/* duplicate correlation  */
-    NativeEndian::write_u32(&mut value[8..12], 0 as u32);
-    options.push(TcOption::Other(DefaultNla::new(kind, value)));
-
-    // distribution data for delays
-    // as a vector of i16
-    let kind = TCA_NETEM_DELAY_DIST;
-    let mut value = vec![0; 2 * distribution.len()];
-    let mut start = 0;
-    for entry in distribution {
-        NativeEndian::write_i16(&mut value[start..start+2], entry.clone());
-        start += 2;
-    }
-    options.push(TcOption::Other(DefaultNla::new(kind, value)));
-
-    // tc_netem_reorder
-    let kind = TCA_NETEM_REORDER;
-    let mut value = vec![0; 8];
-    //__u32	probability;
-    NativeEndian::write_u32(&mut value[0..4], 0 as u32);
-    //__u32	correlation;
-    NativeEndian::write_u32(&mut value[4..8], 0 as u32);
-    options.push(TcOption::Other(DefaultNla::new(kind, value)));
-
-    // tc_netem_corrupt {
-    let kind = TCA_NETEM_CORRUPT;
-    let mut value = vec![0; 8];
-    //__u32	probability;
-    NativeEndian::write_u32(&mut value[0..4], 0 as u32);
-    //__u32	correlation;
-    NativeEndian::write_u32(&mut value[4..8], 0 as u32);
-    options.push(TcOption::Other(DefaultNla::new(kind, value)));
-
-    // tc_netem_rate
-    let kind = TCA_NETEM_RATE;
-    let mut value = vec![0; 16];
-    //__u32	rate;	/* byte/s */
-    NativeEndian::write_u32(&mut value[0..4], 0 as u32);
-    //__s32	packet_overhead;
-    NativeEndian::write_i32(&mut value[4..8], 0 as i32);
-    //__u32	cell_size;
-    NativeEndian::write_u32(&mut value[8..12], 0 as u32);
-    //__s32	cell_overhead;
-    NativeEndian::write_i32(&mut value[12..16], 0 as i32);
-    options.push(TcOption::Other(DefaultNla::new(kind, value)));
-
-    // tc_netem_slot
-    let kind = TCA_NETEM_SLOT;
-    let mut value = vec![0; 42];
-    //__s64   min_delay; /* nsec */
-    NativeEndian::write_i64(&mut value[0..8], 0 as i64);
-    //__s64   max_delay;
-    NativeEndian::write_i64(&mut value[8..16], 0 as i64);
-    //__s32   max_packets;
-    NativeEndian::write_i32(&mut value[16..22], 0 as i32);
-    //__s32   max_bytes;
-    NativeEndian::write_i32(&mut value[22..26], 0 as i32);
-    //__s64	dist_delay; /* nsec */
-    NativeEndian::write_i64(&mut value[26..34], 0 as i64);
-    //__s64	dist_jitter; /* nsec */
-    NativeEndian::write_i64(&mut value[34..42], 0 as i64);
-    options.push(TcOption::Other(DefaultNla::new(kind, value)));
-
-    // 64 bit version of rate in byte/s
-    // max of tc_netem_rate.rate and this is picked
-    // (meaning always this because the prior is 0)
-    let kind = TCA_NETEM_RATE64;
-    let mut value = vec![0; 8];
-    NativeEndian::write_u64(&mut value[0..8], rate as u64);
-    options.push(TcOption::Other(DefaultNla::new(kind, value)));
-
-    // 64 bit version of latency in ns
-    // considering tc_netem_qopt.latency doesn't seem to exist anymore
-    // (makes nla invalid) just use this
-    // why is it signed? I have no idea
-    let kind = TCA_NETEM_LATENCY64;
-    let mut value = vec![0; 8];
-    NativeEndian::write_i64(&mut value[0..8], latency as i64);
-    options.push(TcOption::Other(DefaultNla::new(kind, value)));
-
-    // 64 bit version of jitter in ns
-    // same as TCA_NETEM_LATENCY64 this is the more supported version
-    let kind = TCA_NETEM_JITTER64;
-    let mut value = vec![0; 8];
-    NativeEndian::write_i64(&mut value[0..8], jitter as i64);
-    options.push(TcOption::Other(DefaultNla::new(kind, value)));
-
     // add options and make request
-    request.message_mut().attributes.push(TcAttribute::Options(options));
+    request.message_mut().attributes.push(TcAttribute::Options(config.to_options()));
     let _ = request.execute().await.map_err(|e| e.to_string())?;
 
     // print status
-    println!("[qdisc][netem][{}][{}] \
-        limit: {} pkts, loss: {}%, rate: {} byte/s, \
-        latency: {} ns, jitter: {} ns",
+    println!("[qdisc][netem][{}][{}] {}",
         interface_id,
         if inplace { "changed" } else { "replaced" },
-        limit, loss, rate, latency, jitter
+        config
     );
 
     Ok(())
 }
 
 /**
- * Replace/Create a default fq_codel on interface
+ * TC_H_ROOT from /include/uapi/linux/pkt_sched.h
+ */
+const TC_H_ROOT: u32 = 0xFFFF_FFFF;
+
+/**
+ * TC_H_INGRESS from /include/uapi/linux/pkt_sched.h
+ * parent of the ingress and clsact qdiscs
+ */
+const TC_H_INGRESS: u32 = 0xFFFF_FFF1;
+
+/**
+ * Filter parents of the ingress and egress blocks of ingress/clsact
+ * TC_H_MAKE(TC_H_CLSACT, TC_H_MIN_INGRESS/TC_H_MIN_EGRESS)
+ */
+const TC_H_CLSACT_INGRESS: u32 = 0xFFFF_FFF2;
+const TC_H_CLSACT_EGRESS: u32 = 0xFFFF_FFF3;
+
+/**
+ * tc attributes from /include/uapi/linux/rtnetlink.h
+ * that describe a qdisc, class or filter and can be sent back
+ */
+const TCA_KIND: u16 = 1;
+const TCA_OPTIONS: u16 = 2;
+const TCA_STAB: u16 = 8;
+const TCA_CHAIN: u16 = 11;
+const TCA_INGRESS_BLOCK: u16 = 13;
+const TCA_EGRESS_BLOCK: u16 = 14;
+
+/**
+ * Nested attributes of TCA_STAB from /include/uapi/linux/pkt_sched.h
+ */
+const TCA_STAB_BASE: u16 = 1;
+const TCA_STAB_DATA: u16 = 2;
+
+/**
+ * enum tc_link_layer from /include/uapi/linux/pkt_sched.h
+ */
+const TC_LINKLAYER_ATM: u32 = 2;
+
+/**
+ * Snapshot of the qdisc trees of an interface
+ * Holds the root qdisc, its classes and child qdiscs,
+ * the ingress/clsact qdisc and the filters attached to all of them
+ * so they can be restored later
+ */
+#[derive(Clone)]
+pub struct QdiscSnapshot {
+    interface_id: u32,
+    root: TcMessage,
+    classes: Vec<TcMessage>,
+    children: Vec<TcMessage>,
+    /// ingress or clsact qdisc, None if there was none
+    ingress: Option<TcMessage>,
+    /// filters in dump order with their parent set
+    filters: Vec<TcMessage>,
+}
+
+impl QdiscSnapshot {
+    /**
+     * Whether the root qdisc is the one created by the kernel
+     * these always have handle 0:
+     */
+    pub fn is_default(&self) -> bool {
+        u32::from(self.root.header.handle) == 0
+    }
+}
+
+/**
+ * Size table of a TCA_STAB attribute as tc computes it
+ * The kernel only dumps the tc_sizespec, not the table,
+ * so it is recomputed like tc_calc_size_table() in iproute2
+ * @param spec  struct tc_sizespec as dumped
+ */
+fn stab_table(spec: &[u8]) -> Option<Vec<u16>> {
+    if spec.len() < 24 {
+        return None;
+    }
+    let u32_at = |offset: usize| u32::from_ne_bytes(spec[offset..offset + 4].try_into().unwrap());
+    let cell_log = spec[0] as u32;
+    let size_log = spec[1] as u32;
+    let linklayer = u32_at(8);
+    let mpu = u32_at(12);
+    let tsize = u32_at(20);
+
+    Some((0..tsize).map(|i| {
+        let size = ((i + 1) << cell_log).max(mpu);
+        // ATM pads to whole cells of 48 bytes payload in 53 bytes
+        let size = if linklayer == TC_LINKLAYER_ATM { size.div_ceil(48) * 53 } else { size };
+        (size >> size_log) as u16
+    }).collect())
+}
+
+/**
+ * Rebuild a dumped TCA_STAB attribute with its size table
+ * @param attr  TCA_STAB attribute from a dump
+ */
+fn stab_for_restore(attr: &TcAttribute) -> TcAttribute {
+    let mut dumped = vec![0; attr.value_len()];
+    attr.emit_value(&mut dumped);
+
+    let mut nlas: Vec<DefaultNla> = Vec::new();
+    for nla in NlasIterator::new(dumped.as_slice()).flatten() {
+        if nla.kind() != TCA_STAB_BASE {
+            continue;
+        }
+        nlas.push(DefaultNla::new(TCA_STAB_BASE, nla.value().to_vec()));
+        if let Some(table) = stab_table(nla.value()).filter(|table| !table.is_empty()) {
+            nlas.push(DefaultNla::new(TCA_STAB_DATA,
+                table.iter().flat_map(|size| size.to_ne_bytes()).collect()));
+        }
+    }
+
+    let mut value = vec![0; nlas.as_slice().buffer_len()];
+    nlas.as_slice().emit(&mut value);
+    TcAttribute::Other(DefaultNla::new(TCA_STAB, value))
+}
+
+/**
+ * Strip everything from a dumped tc message
+ * that can't be sent back to the kernel (stats etc.)
+ * @param msg  Message as obtained from a dump
+ */
+fn tc_message_for_restore(msg: &TcMessage) -> TcMessage {
+    let mut msg = msg.clone();
+    msg.attributes = msg.attributes.iter()
+        .filter_map(|attr| match attr.kind() {
+            TCA_KIND | TCA_OPTIONS | TCA_CHAIN | TCA_INGRESS_BLOCK | TCA_EGRESS_BLOCK =>
+                Some(attr.clone()),
+            TCA_STAB => Some(stab_for_restore(attr)),
+            _ => None,
+        })
+        .collect();
+    msg
+}
+
+/**
+ * Send a tc message and wait for the ACK
+ * @param handle   Handle for rtnetlink
+ * @param message  Message to send
+ * @param flags    Additional netlink flags
+ */
+async fn tc_request(
+    mut handle: Handle,
+    message: RouteNetlinkMessage,
+    flags: u16
+) -> Result<(), rtnetlink::Error> {
+    let mut req = NetlinkMessage::from(message);
+    req.header.flags = NLM_F_REQUEST | NLM_F_ACK | flags;
+
+    let mut response = handle.request(req)?;
+    while let Some(message) = response.next().await {
+        if let NetlinkPayload::Error(err) = message.payload {
+            if err.code.is_some() {
+                return Err(rtnetlink::Error::NetlinkError(err));
+            }
+        }
+    }
+    Ok(())
+}
+
+/**
+ * Dump the filters attached to a qdisc or class
+ * The kernel dumps one block per request so every parent is asked for
+ * @param handle        Handle for rtnetlink
+ * @param interface_id  ID of the interface
+ * @param parent        Handle of the qdisc or class
+ */
+async fn filter_dump(
+    mut handle: Handle,
+    interface_id: u32,
+    parent: u32
+) -> Result<Vec<TcMessage>, String> {
+    let mut message = TcMessage::default();
+    message.header.index = interface_id as i32;
+    message.header.parent = parent.into();
+
+    let mut req = NetlinkMessage::from(RouteNetlinkMessage::GetTrafficFilter(message));
+    req.header.flags = NLM_F_REQUEST | NLM_F_DUMP;
+
+    let mut response = handle.request(req).map_err(|e| e.to_string())?;
+    let mut filters: Vec<TcMessage> = Vec::new();
+    while let Some(message) = response.next().await {
+        match message.payload {
+            NetlinkPayload::InnerMessage(RouteNetlinkMessage::NewTrafficFilter(mut filter)) => {
+                // the kernel reports 0 for filters of the root qdisc
+                filter.header.parent = parent.into();
+                filters.push(filter);
+            },
+            NetlinkPayload::Error(err) if err.code.is_some() =>
+                return Err(rtnetlink::Error::NetlinkError(err).to_string()),
+            _ => (),
+        }
+    }
+    Ok(filters)
+}
+
+/**
+ * Take a snapshot of the qdisc trees on interface
  * @param handle        Handle for rtnetlink
  * @param interface_id  ID of the interface
  */
-pub async fn qdisc_fq_codel(
+pub async fn qdisc_snapshot(
     handle: Handle,
     interface_id: u32
+) -> Result<QdiscSnapshot, String> {
+    // dumps return qdiscs of all interfaces
+    let qdiscs: Vec<TcMessage> = handle
+        .qdisc()
+        .get()
+        .index(interface_id as i32)
+        .execute()
+        .try_collect::<Vec<TcMessage>>()
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .filter(|msg| msg.header.index == interface_id as i32)
+        .collect();
+
+    let root = qdiscs.iter()
+        .find(|msg| u32::from(msg.header.parent) == TC_H_ROOT)
+        .cloned()
+        .ok_or(format!("No root qdisc on interface {}", interface_id))?;
+
+    let ingress = qdiscs.iter()
+        .find(|msg| u32::from(msg.header.parent) == TC_H_INGRESS)
+        .cloned();
+
+    let classes: Vec<TcMessage> = handle
+        .traffic_class(interface_id as i32)
+        .get()
+        .execute()
+        .try_collect()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // child qdiscs hang off classes
+    // this also skips ingress/clsact
+    let children: Vec<TcMessage> = qdiscs.into_iter()
+        .filter(|msg| classes.iter().any(|class|
+                u32::from(class.header.handle) == u32::from(msg.header.parent)))
+        .collect();
+
+    // filters can sit on any qdisc and class of the tree
+    // and on the blocks of ingress/clsact
+    let mut parents: Vec<u32> = std::iter::once(&root)
+        .chain(classes.iter())
+        .chain(children.iter())
+        .map(|msg| u32::from(msg.header.handle))
+        .collect();
+    if let Some(ingress) = &ingress {
+        parents.push(TC_H_CLSACT_INGRESS);
+        if tc_message_kind(ingress) == "clsact" {
+            parents.push(TC_H_CLSACT_EGRESS);
+        }
+    }
+    let mut filters: Vec<TcMessage> = Vec::new();
+    for parent in parents {
+        filters.extend(filter_dump(handle.clone(), interface_id, parent).await?);
+    }
+
+    let snapshot = QdiscSnapshot { interface_id, root, classes, children, ingress, filters };
+
+    println!("[qdisc][{}][snapshot] root: {}{}, {} classes, {} child qdiscs, ingress: {}, {} filters",
+        interface_id,
+        tc_message_kind(&snapshot.root),
+        if snapshot.is_default() { " (default)" } else { "" },
+        snapshot.classes.len(),
+        snapshot.children.len(),
+        snapshot.ingress.as_ref().map(tc_message_kind).unwrap_or(String::from("none")),
+        snapshot.filters.len()
+    );
+
+    Ok(snapshot)
+}
+
+/**
+ * Restore the egress qdisc tree from a snapshot
+ * @param handle    Handle for rtnetlink
+ * @param snapshot  Snapshot as obtained by qdisc_snapshot()
+ */
+async fn restore_root(
+    handle: Handle,
+    snapshot: &QdiscSnapshot
 ) -> Result<(), String> {
+    // root qdisc
     let mut request = handle
         .qdisc()
-        .replace(interface_id as i32)
-        .root();
+        .replace(snapshot.interface_id as i32);
+    *request.message_mut() = tc_message_for_restore(&snapshot.root);
+    request.execute().await.map_err(|e| e.to_string())?;
+
+    // classes have to be created after their parents
+    let mut created: Vec<u32> = vec![u32::from(snapshot.root.header.handle)];
+    let mut pending: Vec<&TcMessage> = snapshot.classes.iter().collect();
+    while !pending.is_empty() {
+        let (ready, rest): (Vec<&TcMessage>, Vec<&TcMessage>) = pending.into_iter()
+            .partition(|class| created.contains(&u32::from(class.header.parent)));
+        if ready.is_empty() {
+            return Err(format!("Could not restore {} classes with unknown parents",
+                    rest.len()));
+        }
 
-    request.message_mut().attributes.push(
-        TcAttribute::Kind(String::from("fq_codel")));
+        for class in ready {
+            tc_request(
+                handle.clone(),
+                RouteNetlinkMessage::NewTrafficClass(tc_message_for_restore(class)),
+                NLM_F_CREATE | NLM_F_REPLACE
+            ).await.map_err(|e| e.to_string())?;
+            created.push(u32::from(class.header.handle));
+        }
+        pending = rest;
+    }
 
-    // make request
-    let _ = request.execute().await.map_err(|e| e.to_string())?;
+    // child qdiscs replace the defaults created with the classes
+    for child in snapshot.children.iter() {
+        let mut request = handle
+            .qdisc()
+            .replace(snapshot.interface_id as i32);
+        *request.message_mut() = tc_message_for_restore(child);
+        request.execute().await.map_err(|e| e.to_string())?;
+    }
+
+    Ok(())
+}
+
+/**
+ * Put the ingress/clsact qdisc back as it was in the snapshot
+ * Whatever is there now is deleted with its filters first
+ * @param handle    Handle for rtnetlink
+ * @param snapshot  Snapshot as obtained by qdisc_snapshot()
+ */
+async fn restore_ingress(
+    handle: Handle,
+    snapshot: &QdiscSnapshot
+) -> Result<(), String> {
+    let current = handle
+        .qdisc()
+        .get()
+        .index(snapshot.interface_id as i32)
+        .execute()
+        .try_collect::<Vec<TcMessage>>()
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .find(|msg| msg.header.index == snapshot.interface_id as i32
+            && u32::from(msg.header.parent) == TC_H_INGRESS);
+
+    if let Some(current) = current {
+        let mut request = handle
+            .qdisc()
+            .del(snapshot.interface_id as i32);
+        *request.message_mut() = current;
+        request.message_mut().attributes.clear();
+        request.execute().await.map_err(|e| e.to_string())?;
+    }
+
+    if let Some(ingress) = &snapshot.ingress {
+        let mut request = handle
+            .qdisc()
+            .add(snapshot.interface_id as i32);
+        *request.message_mut() = tc_message_for_restore(ingress);
+        request.execute().await.map_err(|e| e.to_string())?;
+    }
 
-    // print status
-    println!("[qdisc][fq_codel][replaced] default");
     Ok(())
 }
 
+/**
+ * Restore the qdisc trees and filters from a snapshot
+ * If the snapshot holds the kernel default root qdisc
+ * the current root qdisc is deleted so the kernel recreates it
+ * @param handle    Handle for rtnetlink
+ * @param snapshot  Snapshot as obtained by qdisc_snapshot()
+ */
+pub async fn qdisc_restore(
+    handle: Handle,
+    snapshot: &QdiscSnapshot
+) -> Result<(), String> {
+    if snapshot.is_default() {
+        let mut request = handle
+            .qdisc()
+            .del(snapshot.interface_id as i32);
+        *request.message_mut() = snapshot.root.clone();
+        request.message_mut().attributes.clear();
+        request.execute().await.map_err(|e| e.to_string())?;
+
+        println!("[qdisc][{}][deleted] back to kernel default",
+            snapshot.interface_id);
+    } else {
+        restore_root(handle.clone(), snapshot).await?;
+    }
+
+    restore_ingress(handle.clone(), snapshot).await?;
+
+    // filters last, their parents exist now
+    for filter in snapshot.filters.iter() {
+        match tc_request(
+            handle.clone(),
+            RouteNetlinkMessage::NewTrafficFilter(tc_message_for_restore(filter)),
+            NLM_F_CREATE | NLM_F_EXCL
+        ).await {
+            // some filters come with their parent filter
+            // like the root hash table of u32
+            Err(rtnetlink::Error::NetlinkError(err))
+                if err.code.is_some_and(|code| code.get() == -libc::EEXIST) => (),
+            result => result.map_err(|e| e.to_string())?,
+        }
+    }
+
+    println!("[qdisc][{}][restored] root: {}, {} classes, {} child qdiscs, ingress: {}, {} filters",
+        snapshot.interface_id,
+        tc_message_kind(&snapshot.root),
+        snapshot.classes.len(),
+        snapshot.children.len(),
+        snapshot.ingress.as_ref().map(tc_message_kind).unwrap_or(String::from("none")),
+        snapshot.filters.len()
+    );
+
+    Ok(())
+}
+
+/**
+ * Get the kind of a tc message e.g. "fq_codel"
+ * @param msg  tc message
+ */
+fn tc_message_kind(msg: &TcMessage) -> String {
+    msg.attributes.iter()
+        .find_map(|attr| match attr {
+            TcAttribute::Kind(kind) => Some(kind.clone()),
+            _ => None,
+        })
+        .unwrap_or(String::from("unknown"))
+}
+
 /**
  * Upper bound on size of distribution
  * really (TCA_BUF_MAX - other headers) / sizeof (__s16)
@@ -0,0 +1,230 @@
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::process::exit;
+use std::time::Instant;
+
+use crate::capture::{CaptureConfig, PacketCapture};
+use crate::config::RunMetadata;
+use crate::metrics::{MetricsConfig, ThroughputRecorder};
+use crate::netem::NetemConfig;
+use crate::ping;
+use crate::tcp_info::{self, Side};
+use crate::testbed::{self, Testbed, TestbedConfig};
+use crate::trace::{self, Playback, Trace};
+use crate::webserver;
+
+/**
+ * What the hooks of a scenario get to work with
+ */
+pub struct Context<'a> {
+    pub testbed: &'a Testbed,
+    pub metrics: &'a MetricsConfig,
+    /// start of the trace, common clock for trace playback and metrics
+    pub start: Instant,
+    /// address servers listen on, dual stack :: with IPv6 and 0.0.0.0 without
+    /// as binding :: fails where IPv6 is disabled
+    pub bind_address: IpAddr,
+}
+
+/**
+ * A workload played between the two namespaces of a testbed
+ * Server and client run in their own child processes inside a tokio
+ * runtime and are killed when the trace is done, the runner takes care
+ * of the testbed, capture, ping, trace playback and teardown
+ */
+pub trait Scenario {
+    /// name of the server process in log messages
+    const SERVER: &'static str = "webserver";
+    /// name of the client process in log messages
+    const CLIENT: &'static str = "webclient";
+    /// start the client before the server
+    /// for servers that send to a client without being asked
+    const CLIENT_FIRST: bool = false;
+
+    /**
+     * Check the settings before the testbed is set up
+     */
+    fn validate(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    /**
+     * Run the server side in namespace 1 until killed
+     */
+    fn server(&self, ctx: &Context) -> impl Future<Output = Result<(), String>>;
+
+    /**
+     * Run the client side in namespace 2 until killed
+     */
+    fn client(&self, ctx: &Context) -> impl Future<Output = Result<(), String>>;
+
+    /**
+     * Called in the parent after trace playback
+     * before the testbed is torn down
+     */
+    fn cleanup(&self, _ctx: &Context) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/**
+ * Web server with TCP_INFO sampling of its connections
+ * the server side of the HTTP scenarios
+ * @param ctx      Context of the run
+ * @param uploads  Recorders for the bytes received per upload flow
+ */
+pub async fn web_server(ctx: &Context<'_>, uploads: Vec<ThroughputRecorder>) -> Result<(), String> {
+    tcp_info::start_sampler(ctx.metrics, ctx.start, Side::Server, webserver::PORT)?;
+    webserver::rocket_main(ctx.bind_address, uploads).await
+}
+
+/**
+ * Url of a path on the web server from namespace 2
+ * @param testbed  Testbed of the run
+ * @param path     Path without leading slash
+ */
+pub fn web_url(testbed: &Testbed, path: &str) -> String {
+    format!("http://{}:{}/{}", testbed.server_host(), webserver::PORT, path)
+}
+
+/**
+ * Fork a child running one side of a scenario
+ * inside a namespace and its own tokio runtime
+ * @param ctx   Context of the run
+ * @param name  Name of the child for log messages
+ * @param ns2   Run in namespace 2 instead of 1
+ * @param side  Future of the side to run
+ */
+fn fork_side<F, Fut>(ctx: &Context, name: &str, ns2: bool, side: F)
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<(), String>>,
+{
+    let ns = if ns2 { &ctx.testbed.ns2 } else { &ctx.testbed.ns1 };
+    if let Err(e) = ctx.testbed.fork_child(name, || {
+        let _ = ns.run(|_| {
+            let rt = tokio::runtime::Builder::new_multi_thread()
+                .thread_name(name)
+                .enable_all()
+                // increased stack size for big chunks
+                .thread_stack_size(100 * 1024 * 1024)
+                .build()
+                .expect("[test] failed to create tokio runtime");
+            if let Err(e) = rt.block_on(side()) {
+                eprintln!("[test] {}: {}", name, e);
+            }
+        });
+    }) {
+        eprintln!("[test] {}", e);
+    }
+}
+
+/**
+ * Settings of a run besides the workload and the trace
+ * built from the experiment
+ */
+pub struct RunSettings {
+    /// delay distribution for netem
+    pub distribution_file: Option<String>,
+    /// netem settings that stay the same
+    pub netem: NetemConfig,
+    /// topology of the testbed
+    pub testbed: TestbedConfig,
+    /// packet capture settings, None to not capture
+    pub capture: Option<CaptureConfig>,
+    pub metrics: MetricsConfig,
+    /// run metadata, written once the testbed is set up
+    pub metadata: Option<RunMetadata>,
+}
+
+/**
+ * Run a scenario while playing back a trace
+ * @param scenario  Workload to run
+ * @param trace     Trace to play back
+ * @param settings  Settings of the run
+ */
+pub fn run<S: Scenario>(scenario: S, trace: &mut Trace, settings: RunSettings) {
+    let RunSettings {
+        distribution_file,
+        netem,
+        testbed: testbed_config,
+        capture,
+        metrics,
+        metadata,
+    } = settings;
+
+    if let Err(e) = scenario.validate() {
+        eprintln!("[test] {}", e);
+        exit(1);
+    }
+
+    // setup testbed
+    let testbed = testbed::Testbed::new(&testbed_config).unwrap_or_else(|e| {
+        eprintln!("[test] Setting up testbed failed: {}", e);
+        exit(1);
+    });
+
+    // with the congestion control the namespaces actually use
+    if let Some(Err(e)) = metadata.map(|metadata| metadata.write(&testbed.congestion_control)) {
+        eprintln!("[test] {}", e);
+        drop(testbed);
+        exit(1);
+    }
+
+    let ctx = Context {
+        testbed: &testbed,
+        metrics: &metrics,
+        start: Instant::now(),
+        bind_address: if testbed.ipv6 {
+            IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+        } else {
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+        },
+    };
+
+    // start server in namespace 1 and client in namespace 2
+    if S::CLIENT_FIRST {
+        fork_side(&ctx, S::CLIENT, true, || scenario.client(&ctx));
+        fork_side(&ctx, S::SERVER, false, || scenario.server(&ctx));
+    } else {
+        fork_side(&ctx, S::SERVER, false, || scenario.server(&ctx));
+        fork_side(&ctx, S::CLIENT, true, || scenario.client(&ctx));
+    }
+
+    // RTT probe from ns2 to ns1 next to the workload
+    ping::start_prober(&testbed, &metrics, ctx.start);
+
+    // capture on both ends of the veth pair and the ifb
+    // threads are started after forking the children
+    let capture = capture.and_then(|capture| {
+        PacketCapture::start(&capture, ctx.start, &[
+            (&testbed.ns1, testbed.if1.clone()),
+            (&testbed.ns2, testbed.if2.clone()),
+            (&testbed.ns2, testbed.ifb2.clone()),
+        ]).map_err(|e| eprintln!("[test] Starting packet capture failed: {}", e)).ok()
+    });
+
+    // start playback of the trace
+    let playback = Playback {
+        distribution_file,
+        netem,
+        start: ctx.start,
+        event_log: metrics.event_log_file.clone(),
+        capture: capture.as_ref(),
+    };
+    let _ = testbed.ns2.run(|_| {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(trace::run_trace(trace, playback, testbed.if2.clone(),
+            Some(testbed.ifb2.clone())));
+    });
+
+    if let Some(capture) = capture {
+        capture.stop();
+    }
+
+    if let Err(e) = scenario.cleanup(&ctx) {
+        eprintln!("[test] {}", e);
+    }
+
+    // testbed and child processes are torn down on drop
+}
@@ -0,0 +1,243 @@
+use netlink_packet_utils::byteorder::{ByteOrder, NativeEndian};
+use serde::Serialize;
+use std::os::fd::RawFd;
+use std::time::Instant;
+
+use crate::metrics::{unix_time, MetricsConfig, MetricsWriter};
+
+/**
+ * Size of struct tcp_info from /include/uapi/linux/tcp.h
+ * up to tcpi_snd_wnd, older kernels return less
+ */
+const TCP_INFO_LEN: usize = 232;
+
+/**
+ * TCP_INFINITE_SSTHRESH from /include/net/tcp.h
+ */
+const TCP_INFINITE_SSTHRESH: u32 = 0x7fff_ffff;
+
+/**
+ * Which end of the connection is sampled
+ */
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    /// sockets connected to the port
+    Client,
+    /// sockets accepted on the port
+    Server,
+}
+
+impl std::fmt::Display for Side {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Side::Client => write!(f, "client"),
+            Side::Server => write!(f, "server"),
+        }
+    }
+}
+
+/**
+ * A single TCP_INFO sample of one socket
+ */
+#[derive(Serialize)]
+struct TcpInfoSample {
+    /// seconds since the start of the trace
+    time: f64,
+    /// seconds since the unix epoch
+    unix_time: f64,
+    side: Side,
+    local_port: u16,
+    remote_port: u16,
+    /// TCP_ESTABLISHED etc.
+    state: u8,
+    /// congestion avoidance state (open, disorder, cwr, recovery, loss)
+    ca_state: u8,
+    /// congestion window in segments
+    cwnd: u32,
+    /// slow start threshold in segments, empty while still infinite
+    ssthresh: Option<u32>,
+    /// retransmission timeout in ms
+    rto: f64,
+    /// smoothed RTT in ms
+    rtt: f64,
+    /// RTT variance in ms
+    rttvar: f64,
+    /// minimum RTT in ms
+    min_rtt: Option<f64>,
+    /// unrecovered retransmissions of the current RTO
+    retransmits: u8,
+    /// retransmitted segments over the whole connection
+    total_retrans: u32,
+    /// segments in flight
+    unacked: u32,
+    /// segments considered lost
+    lost: u32,
+    /// pacing rate in mbit/s
+    pacing_rate: Option<f64>,
+    /// delivery rate in mbit/s
+    delivery_rate: Option<f64>,
+    bytes_acked: Option<u64>,
+    bytes_received: Option<u64>,
+    bytes_retrans: Option<u64>,
+}
+
+/**
+ * Read the local and remote port of a socket
+ * @param fd  Socket file descriptor
+ */
+fn socket_ports(fd: RawFd) -> Option<(u16, u16)> {
+    let port = |getname: unsafe extern "C" fn(
+        libc::c_int, *mut libc::sockaddr, *mut libc::socklen_t
+    ) -> libc::c_int| {
+        let mut addr: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+        let mut len = std::mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+        let ret = unsafe {
+            getname(fd, &mut addr as *mut _ as *mut libc::sockaddr, &mut len)
+        };
+        if ret != 0 {
+            return None;
+        }
+        match addr.ss_family as libc::c_int {
+            libc::AF_INET => {
+                let addr = unsafe { &*(&addr as *const _ as *const libc::sockaddr_in) };
+                Some(u16::from_be(addr.sin_port))
+            },
+            libc::AF_INET6 => {
+                let addr = unsafe { &*(&addr as *const _ as *const libc::sockaddr_in6) };
+                Some(u16::from_be(addr.sin6_port))
+            },
+            _ => None,
+        }
+    };
+
+    Some((port(libc::getsockname)?, port(libc::getpeername)?))
+}
+
+/**
+ * Read TCP_INFO of a socket
+ * fails for anything that isn't a TCP socket
+ * @param fd  Socket file descriptor
+ */
+fn tcp_info(fd: RawFd) -> Option<Vec<u8>> {
+    let mut buf = vec![0u8; TCP_INFO_LEN];
+    let mut len = TCP_INFO_LEN as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(fd, libc::IPPROTO_TCP, libc::TCP_INFO,
+            buf.as_mut_ptr() as *mut libc::c_void, &mut len)
+    };
+    if ret != 0 {
+        return None;
+    }
+    buf.truncate(len as usize);
+    Some(buf)
+}
+
+impl TcpInfoSample {
+    /**
+     * Parse a raw struct tcp_info
+     * fields the kernel didn't return are left empty
+     * @param info  Buffer as returned by getsockopt
+     */
+    fn new(time: f64, side: Side, ports: (u16, u16), info: &[u8]) -> Self {
+        let u32_at = |offset: usize| info.get(offset..offset + 4).map(NativeEndian::read_u32);
+        let u64_at = |offset: usize| info.get(offset..offset + 8).map(NativeEndian::read_u64);
+        let us_to_ms = |us: u32| us as f64 / 1000.0;
+        let mbit = |rate: u64| rate as f64 * 8.0 / 1_000_000.0;
+
+        Self {
+            time,
+            unix_time: unix_time(),
+            side,
+            local_port: ports.0,
+            remote_port: ports.1,
+            state: info[0],
+            ca_state: info[1],
+            retransmits: info[2],
+            rto: us_to_ms(u32_at(8).unwrap_or(0)),
+            unacked: u32_at(24).unwrap_or(0),
+            lost: u32_at(32).unwrap_or(0),
+            rtt: us_to_ms(u32_at(68).unwrap_or(0)),
+            rttvar: us_to_ms(u32_at(72).unwrap_or(0)),
+            ssthresh: u32_at(76).filter(|ssthresh| *ssthresh < TCP_INFINITE_SSTHRESH),
+            cwnd: u32_at(80).unwrap_or(0),
+            total_retrans: u32_at(100).unwrap_or(0),
+            pacing_rate: u64_at(104).map(mbit),
+            bytes_acked: u64_at(120),
+            bytes_received: u64_at(128),
+            min_rtt: u32_at(148).map(us_to_ms),
+            delivery_rate: u64_at(160).map(mbit),
+            bytes_retrans: u64_at(208),
+        }
+    }
+}
+
+/**
+ * Periodically sample TCP_INFO of all sockets of this process
+ * belonging to port and write them to the tcp info file
+ * Has to be called inside a tokio runtime of the process owning the sockets
+ * @param config  Metrics config, nothing is sampled without a tcp info file
+ * @param start   Start of the trace, samples are timed relative to it
+ * @param side    Whether this is the client or server end
+ * @param port    Server port of the connections
+ */
+pub fn start_sampler(
+    config: &MetricsConfig,
+    start: Instant,
+    side: Side,
+    port: u16
+) -> Result<(), String> {
+    let Some(path) = config.tcp_info_file(side) else {
+        return Ok(());
+    };
+    let mut writer = MetricsWriter::create(path.as_str())?;
+    let interval = config.interval;
+    println!("[tcp_info] Sampling {} sockets every {}ms to {}",
+        side, interval.as_millis(), path);
+
+    tokio::spawn(async move {
+        let start = tokio::time::Instant::from_std(start);
+        let mut ticker = tokio::time::interval_at(start + interval, interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+            let time = start.elapsed().as_secs_f64();
+
+            // sockets show up as "socket:[inode]" links
+            let Ok(fds) = std::fs::read_dir("/proc/self/fd") else {
+                continue;
+            };
+            for entry in fds.flatten() {
+                let is_socket = std::fs::read_link(entry.path())
+                    .is_ok_and(|target| target.to_string_lossy().starts_with("socket:"));
+                let Some(fd) = entry.file_name().to_str()
+                    .and_then(|name| name.parse::<RawFd>().ok())
+                    .filter(|_| is_socket) else {
+                    continue;
+                };
+
+                let Some(ports) = socket_ports(fd) else {
+                    continue;
+                };
+                let matches = match side {
+                    Side::Client => ports.1 == port,
+                    Side::Server => ports.0 == port,
+                };
+                if !matches {
+                    continue;
+                }
+                let Some(info) = tcp_info(fd) else {
+                    continue;
+                };
+
+                if let Err(e) = writer.write(&TcpInfoSample::new(time, side, ports, &info)) {
+                    eprintln!("[tcp_info] {}", e);
+                    return;
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
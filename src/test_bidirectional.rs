@@ -0,0 +1,45 @@
+use crate::metrics::{MetricsConfig, ThroughputRecorder};
+use crate::scenario::{self, Context, Scenario};
+use crate::tcp_info::{self, Side};
+use crate::webclient;
+use crate::webserver;
+
+/**
+ * Download and upload of infinite streams of data
+ * over two connections at the same time
+ */
+pub struct Bidirectional;
+
+/**
+ * Recorder for one direction writing next to the throughput file
+ * @param direction  download or upload
+ */
+fn recorder(ctx: &Context, direction: &str) -> Result<ThroughputRecorder, String> {
+    let metrics = MetricsConfig {
+        throughput_file: ctx.metrics.direction_throughput_file(direction),
+        ..ctx.metrics.clone()
+    };
+    ThroughputRecorder::start(&metrics, ctx.start)
+}
+
+impl Scenario for Bidirectional {
+    /**
+     * Web server counting the received bytes of the upload
+     */
+    async fn server(&self, ctx: &Context<'_>) -> Result<(), String> {
+        let upload = recorder(ctx, "upload")?;
+        scenario::web_server(ctx, vec![upload]).await
+    }
+
+    async fn client(&self, ctx: &Context<'_>) -> Result<(), String> {
+        let download = recorder(ctx, "download")?;
+        tcp_info::start_sampler(ctx.metrics, ctx.start, Side::Client, webserver::PORT)?;
+
+        let url = scenario::web_url(ctx.testbed, "infinite-data");
+        tokio::try_join!(
+            webclient::download(url.clone(), download),
+            webclient::upload(url.clone()),
+        ).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
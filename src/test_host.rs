@@ -1,54 +1,84 @@
-use csv::Reader;
-use std::fs::File;
 use std::process::exit;
+use std::time::Instant;
 
-use crate::rtnetlink_utils::{get_interface_id_by_name,qdisc_fq_codel};
-use crate::trace;
+use crate::rtnetlink_utils::{get_interface_id_by_name,qdisc_restore,qdisc_snapshot,QdiscSnapshot};
+use crate::scenario::RunSettings;
+use crate::testbed::current_congestion_control;
+use crate::trace::{self, Playback, Trace};
 
 /**
- * Cleanup funktion that:
- * - resets qdisc to fq_codel
- *
- * TODO:
- * - reset to actual prior qdisc
- *
- * @param interface  Interface name to reset qdisc on
+ * Snapshot the current qdisc of an interface
+ * @param interface  Interface name to snapshot the qdisc of
  */
-async fn cleanup(interface: String) {
+async fn snapshot(interface: String) -> QdiscSnapshot {
     let (connection, handle, _) = rtnetlink::new_connection().unwrap();
     tokio::spawn(connection);
     let interface_id = get_interface_id_by_name(
         handle.clone(), interface.clone())
         .await.unwrap();
 
-    let _ = qdisc_fq_codel(handle, interface_id)
-        .await.unwrap();
+    qdisc_snapshot(handle, interface_id)
+        .await.unwrap()
+}
+
+/**
+ * Cleanup funktion that:
+ * - restores the qdisc from before the trace
+ *
+ * @param snapshot  Snapshot of the qdisc before the trace
+ */
+async fn cleanup(snapshot: QdiscSnapshot) -> Result<(), String> {
+    let (connection, handle, _) = rtnetlink::new_connection()
+        .map_err(|e| format!("Could not open netlink connection: {}", e))?;
+    tokio::spawn(connection);
+
+    qdisc_restore(handle, &snapshot).await
 }
 
 /**
  * Run this test module
- * @param rdr        CSV reader of the trace file
- * @param unterface  Interface name used for trace playback
+ * @param trace      Trace to play back, only the uplink is used
+ * @param interface  Interface name used for trace playback
+ * @param settings   Settings of the run, the testbed and capture are not used
+ *                   and the metadata is written with the host congestion control
  */
-pub fn run_test(
-    rdr: &mut Reader<File>,
-    distribution_file: Option<String>,
-    interface: String
-) {
+pub fn run_test(trace: &mut Trace, interface: String, settings: RunSettings) {
+    let RunSettings { distribution_file, netem, metrics, metadata, .. } = settings;
+    if let Some(Err(e)) = metadata.map(|metadata| current_congestion_control()
+        .and_then(|congestion_control| metadata.write(&congestion_control))) {
+        eprintln!("[test] {}", e);
+        exit(1);
+    }
+
+    // remember the qdisc we are about to replace
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let prior = rt.block_on(snapshot(interface.clone()));
+
     // shutdown handler
-    let iface = interface.clone();
+    let snap = prior.clone();
     ctrlc::set_handler(move || {
         let rt = tokio::runtime::Runtime::new().unwrap();
-        rt.block_on(cleanup(iface.clone()));
+        if let Err(e) = rt.block_on(cleanup(snap.clone())) {
+            eprintln!("[test] Restoring the qdisc failed: {}", e);
+        }
         exit(1);
     }).expect("Error setting Ctrl-C handler");
 
     // start playback of the trace
     let rt = tokio::runtime::Runtime::new().unwrap();
-    rt.block_on(trace::run_trace(rdr, distribution_file.clone(),
-        interface.clone(), None));
+    let playback = Playback {
+        distribution_file,
+        netem,
+        start: Instant::now(),
+        event_log: metrics.event_log_file,
+        capture: None,
+    };
+    rt.block_on(trace::run_trace(trace, playback, interface.clone(), None));
 
     // cleanup when trace is done
     let rt = tokio::runtime::Runtime::new().unwrap();
-    rt.block_on(cleanup(interface.clone()));
+    if let Err(e) = rt.block_on(cleanup(prior)) {
+        eprintln!("[test] Restoring the qdisc failed: {}", e);
+        exit(1);
+    }
 }
@@ -0,0 +1,38 @@
+use crate::metrics::ThroughputRecorder;
+use crate::scenario::{Context, Scenario};
+use crate::udp_probe;
+
+/**
+ * UDP packets at a constant rate from ns1 to ns2
+ */
+pub struct Udp {
+    /// rate in mbit/s
+    pub rate: f64,
+    /// UDP payload size in bytes
+    pub size: usize,
+}
+
+impl Scenario for Udp {
+    const SERVER: &'static str = "udp sender";
+    const CLIENT: &'static str = "udp receiver";
+    // the receiver should be up before the first packet and is
+    // killed first so the sender still runs while it sums up the loss
+    const CLIENT_FIRST: bool = true;
+
+    fn validate(&self) -> Result<(), String> {
+        udp_probe::validate(self.rate, self.size)
+    }
+
+    async fn server(&self, ctx: &Context<'_>) -> Result<(), String> {
+        let target = format!("{}:{}", ctx.testbed.client_host(), udp_probe::PORT)
+            .parse()
+            .map_err(|e| format!("Invalid receiver address: {}", e))?;
+        tokio::task::block_in_place(|| udp_probe::send(target, self.rate, self.size, ctx.start))
+    }
+
+    async fn client(&self, ctx: &Context<'_>) -> Result<(), String> {
+        let recorder = ThroughputRecorder::start(ctx.metrics, ctx.start)?;
+        udp_probe::receive(ctx.start, ctx.bind_address, udp_probe::interval(self.rate, self.size),
+            recorder, ctx.metrics.packet_log_file.clone()).await
+    }
+}
@@ -1,5 +1,96 @@
 use netns_rs::NetNs;
-use std::process::{Command, Stdio};
+use fork::{fork, Fork};
+use nix::sys::signal::{self, SigHandler, Signal};
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::{setpgid, Pid};
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::os::unix::io::AsRawFd;
+use std::panic::AssertUnwindSafe;
+use std::path::PathBuf;
+use std::process::exit;
+use std::str::FromStr;
+use std::sync::{Mutex, Once};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::sleep;
+use std::time::Duration;
+use netlink_packet_route::link::{InfoKind, LinkAttribute, LinkInfo};
+use rtnetlink::Handle;
+
+use crate::rtnetlink_utils::get_interface_id_by_name;
+
+/**
+ * ETH_P_ALL from /include/uapi/linux/if_ether.h
+ */
+const ETH_P_ALL: u16 = 0x0003;
+
+/**
+ * Parent handle of the ingress qdisc (ffff:)
+ */
+const TC_H_INGRESS_PARENT: u32 = 0xFFFF_0000;
+
+/**
+ * Directory for the lock files claiming testbed prefixes
+ */
+const LOCK_DIR: &str = "/run/bridge-loss-emu";
+
+/**
+ * Prefix for automatically allocated testbeds
+ * followed by a number
+ */
+const AUTO_PREFIX: &str = "ble";
+
+/**
+ * Upper bound on automatically allocated testbeds
+ */
+const MAX_INSTANCES: u32 = 100;
+
+/**
+ * Max length of an interface name (IFNAMSIZ - 1)
+ */
+const IFNAMSIZ: usize = 15;
+
+/**
+ * Topology of a Testbed
+ * namespaces are named <prefix>-ns1 and <prefix>-ns2,
+ * interfaces <prefix>-<interface name>
+ */
+#[derive(Clone, Debug)]
+pub struct TestbedConfig {
+    /// Name prefix, allocated automatically if None
+    pub prefix: Option<String>,
+    /// Subnet in CIDR notation, ns1 gets the 1st and ns2 the 2nd host
+    pub subnet: String,
+    /// IPv6 subnet in CIDR notation, assigned like subnet
+    pub subnet6: String,
+    /// Use IPv6 instead of IPv4 for the workloads
+    pub ipv6: bool,
+    /// Interface in ns1
+    pub if1: String,
+    /// Interface in ns2
+    pub if2: String,
+    /// ifb handling incoming traffic of if2
+    pub ifb2: String,
+    /// TCP congestion control for both namespaces
+    /// kernel default if None
+    pub congestion_control: Option<String>,
+}
+
+impl Default for TestbedConfig {
+    fn default() -> Self {
+        Self {
+            prefix: None,
+            subnet: String::from("10.0.0.0/24"),
+            subnet6: String::from("fd00::/64"),
+            ipv6: false,
+            if1: String::from("veth1"),
+            if2: String::from("veth2"),
+            ifb2: String::from("ifb2"),
+            congestion_control: None,
+        }
+    }
+}
 
 pub struct Testbed {
     pub ns1: NetNs,
@@ -9,141 +100,628 @@ pub struct Testbed {
     pub ifb2: String,
     pub addr1: String,
     pub addr2: String,
+    pub addr1_6: String,
+    pub addr2_6: String,
+    pub ipv6: bool,
+    /// TCP congestion control in effect in both namespaces
+    pub congestion_control: String,
+    id: usize,
+    owner: u32,
 }
 
-impl Testbed {
-    pub fn new() -> Testbed {
-        // delete namespaces if they exist
-        for name in ["ns1", "ns2"] {
-            let ns = NetNs::get(name);
-            if ns.is_ok() {
-                println!("[testbed] {} exists - recreating", name);
-                let _ = ns.unwrap().remove();
+/**
+ * Run an async rtnetlink operation in the current network namespace
+ * The runtime is single threaded so everything stays on the
+ * calling thread which is the one that got moved into the namespace
+ * @param f  Closure getting a Handle connected in the current namespace
+ */
+fn in_current_ns<F, Fut, T>(f: F) -> Result<T, String>
+where
+    F: FnOnce(Handle) -> Fut,
+    Fut: std::future::Future<Output = Result<T, String>>,
+{
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| format!("Failed to create tokio runtime: {}", e))?;
+
+    rt.block_on(async {
+        let (connection, handle, _) = rtnetlink::new_connection()
+            .map_err(|e| format!("Failed to open rtnetlink connection: {}", e))?;
+        tokio::spawn(connection);
+        f(handle).await
+    })
+}
+
+/**
+ * Run an async rtnetlink operation in a network namespace
+ * @param ns  Network namespace
+ * @param f   Closure getting a Handle connected in ns
+ */
+fn in_ns<F, Fut, T>(ns: &NetNs, f: F) -> Result<T, String>
+where
+    F: FnOnce(Handle) -> Fut,
+    Fut: std::future::Future<Output = Result<T, String>>,
+{
+    ns.run(|_| in_current_ns(f))
+        .map_err(|e| format!("Failed to enter network namespace {}: {}",
+                ns.path().display(), e))?
+}
+
+/**
+ * Default TCP congestion control of a network namespace
+ * new sockets use it unless they set TCP_CONGESTION
+ */
+const TCP_CONGESTION_CONTROL: &str = "/proc/sys/net/ipv4/tcp_congestion_control";
+
+/**
+ * Read the default TCP congestion control of the calling thread's namespace
+ * new namespaces start with the one of the host
+ */
+pub fn current_congestion_control() -> Result<String, String> {
+    fs::read_to_string(TCP_CONGESTION_CONTROL)
+        .map(|name| String::from(name.trim()))
+        .map_err(|e| format!("Failed to read {}: {}", TCP_CONGESTION_CONTROL, e))
+}
+
+/**
+ * Read the default TCP congestion control of a namespace
+ * @param ns  Network namespace
+ */
+fn get_congestion_control(ns: &NetNs) -> Result<String, String> {
+    ns.run(|_| current_congestion_control())
+        .map_err(|e| format!("Failed to enter network namespace {}: {}",
+                ns.path().display(), e))?
+}
+
+/**
+ * Set the default TCP congestion control of a namespace
+ * the kernel loads the tcp_<name> module if needed
+ * @param ns    Network namespace
+ * @param name  Algorithm e.g. cubic, bbr, reno
+ */
+fn set_congestion_control(ns: &NetNs, name: &str) -> Result<(), String> {
+    ns.run(|_| fs::write(TCP_CONGESTION_CONTROL, name))
+        .map_err(|e| format!("Failed to enter network namespace {}: {}",
+                ns.path().display(), e))?
+        .map_err(|e| format!("Failed to set congestion control {} in {}: {}",
+                name, ns.path().display(), e))?;
+
+    let current = get_congestion_control(ns)?;
+    if current != name {
+        return Err(format!("Congestion control in {} is {} instead of {}",
+                ns.path().display(), current, name));
+    }
+    Ok(())
+}
+
+/**
+ * Parse an address in CIDR notation e.g. 10.0.0.1/24
+ * @param cidr  Address with prefix length
+ */
+fn parse_cidr(cidr: &str) -> Result<(IpAddr, u8), String> {
+    let (addr, prefix_len) = cidr.split_once("/")
+        .ok_or(format!("Missing prefix length in address {}", cidr))?;
+    let addr = IpAddr::from_str(addr)
+        .map_err(|e| format!("Could not parse address {}: {}", cidr, e))?;
+    let prefix_len = u8::from_str(prefix_len)
+        .map_err(|e| format!("Could not parse prefix length {}: {}", cidr, e))?;
+    Ok((addr, prefix_len))
+}
+
+/**
+ * Delete an interface if it exists
+ * @param handle  Handle for rtnetlink
+ * @param name    Name of the interface
+ */
+async fn link_delete_if_exists(handle: Handle, name: String) -> Result<(), String> {
+    if let Ok(id) = get_interface_id_by_name(handle.clone(), name.clone()).await {
+        println!("[testbed] Removing existing interface {}", name);
+        handle.link().del(id).execute().await
+            .map_err(|e| format!("Failed to delete interface {}: {}", name, e))?;
+    }
+    Ok(())
+}
+
+/**
+ * Move an interface into a network namespace
+ * @param handle  Handle for rtnetlink
+ * @param name    Name of the interface
+ * @param ns      Target network namespace
+ */
+async fn link_set_ns(handle: Handle, name: String, ns: &NetNs) -> Result<(), String> {
+    let id = get_interface_id_by_name(handle.clone(), name.clone()).await?;
+    let ns_file = File::open(ns.path())
+        .map_err(|e| format!("Failed to open network namespace {}: {}",
+                ns.path().display(), e))?;
+    handle.link().set(id).setns_by_fd(ns_file.as_raw_fd()).execute().await
+        .map_err(|e| format!("Failed to move interface {} to {}: {}",
+                name, ns.path().display(), e))
+}
+
+/**
+ * Assign addresses to an interface and set it UP
+ * @param handle  Handle for rtnetlink
+ * @param name    Name of the interface
+ * @param addrs   Addresses in CIDR notation
+ */
+async fn link_setup(handle: Handle, name: String, addrs: Vec<String>) -> Result<(), String> {
+    let id = get_interface_id_by_name(handle.clone(), name.clone()).await?;
+
+    // IPv6 addresses would be unusable until duplicate address detection
+    // finished which is pointless on a veth pair
+    // /proc/sys/net belongs to the namespace of the calling thread
+    let accept_dad = format!("/proc/sys/net/ipv6/conf/{}/accept_dad", name);
+    fs::write(&accept_dad, "0")
+        .map_err(|e| format!("Failed to disable DAD via {}: {}", accept_dad, e))?;
+
+    // setup addresses
+    for addr in addrs {
+        let (ip, prefix_len) = parse_cidr(addr.as_str())?;
+        handle.address().add(id, ip, prefix_len).execute().await
+            .map_err(|e| format!("Failed to add address {} to {}: {}", addr, name, e))?;
+    }
+
+    // set UP
+    handle.link().set(id).up().execute().await
+        .map_err(|e| format!("Failed to set {} up: {}", name, e))
+}
+
+/**
+ * Create an ifb interface and redirect all incoming traffic of interface to it
+ * @param handle  Handle for rtnetlink
+ * @param name    Name of the interface
+ * @param ifb     Name of the ifb interface
+ */
+async fn ifb_setup(handle: Handle, name: String, ifb: String) -> Result<(), String> {
+    // ip link add name IFB type ifb
+    let mut request = handle.link().add();
+    request.message_mut().attributes.push(LinkAttribute::IfName(ifb.clone()));
+    request.message_mut().attributes.push(
+        LinkAttribute::LinkInfo(vec![LinkInfo::Kind(InfoKind::Ifb)]));
+    request.execute().await
+        .map_err(|e| format!("Failed to create ifb interface {}: {}", ifb, e))?;
+
+    let ifb_id = get_interface_id_by_name(handle.clone(), ifb.clone()).await?;
+    handle.link().set(ifb_id).up().execute().await
+        .map_err(|e| format!("Failed to set {} up: {}", ifb, e))?;
+
+    // tc qdisc add dev IF ingress
+    let id = get_interface_id_by_name(handle.clone(), name.clone()).await?;
+    handle.qdisc().add(id as i32).ingress().execute().await
+        .map_err(|e| format!("Failed to add ingress qdisc to {}: {}", name, e))?;
+
+    // tc filter add dev IF parent ffff: protocol all u32 match u32 0 0
+    //     action mirred egress redirect dev IFB
+    // all ethertypes so IPv6 (and ARP/NDP) see the same conditions as IPv4
+    handle.traffic_filter(id as i32)
+        .add()
+        .parent(TC_H_INGRESS_PARENT)
+        .protocol(ETH_P_ALL.to_be())
+        .redirect(ifb_id)
+        .map_err(|e| format!("Failed to build redirect filter: {}", e))?
+        .execute().await
+        .map_err(|e| format!("Failed to add redirect filter from {} to {}: {}",
+                name, ifb, e))
+}
+
+/**
+ * Set an interface DOWN and delete it
+ * @param handle  Handle for rtnetlink
+ * @param name    Name of the interface
+ */
+async fn link_teardown(handle: Handle, name: String) -> Result<(), String> {
+    let id = get_interface_id_by_name(handle.clone(), name.clone()).await?;
+
+    // set DOWN
+    handle.link().set(id).down().execute().await
+        .map_err(|e| format!("Failed to set {} down: {}", name, e))?;
+
+    // remove interface
+    handle.link().del(id).execute().await
+        .map_err(|e| format!("Failed to delete interface {}: {}", name, e))
+}
+
+/**
+ * Everything that has to be torn down for a Testbed
+ * kept in a global registry so the signal handler can reach it
+ */
+struct Registration {
+    id: usize,
+    ns1: String,
+    ns2: String,
+    if1: String,
+    ifb2: String,
+    /// flock()ed lock file of the prefix, unlocked on drop
+    lock: File,
+    children: Vec<i32>,
+}
+
+static REGISTRY: Mutex<Vec<Registration>> = Mutex::new(Vec::new());
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+static SIGNAL_HANDLER: Once = Once::new();
+
+/**
+ * Install the shared signal handler (once per process)
+ * that tears down all registered testbeds on Ctrl-C/SIGTERM
+ */
+fn install_signal_handler() {
+    SIGNAL_HANDLER.call_once(|| {
+        let result = ctrlc::set_handler(|| {
+            println!("[testbed] Caught signal - tearing down");
+            let registrations: Vec<Registration> = REGISTRY.lock()
+                .map(|mut registry| registry.drain(..).collect())
+                .unwrap_or_default();
+            for registration in registrations {
+                teardown(registration);
+            }
+            exit(1);
+        });
+        if let Err(e) = result {
+            eprintln!("[testbed] Setting signal handler failed: {}", e);
+        }
+    });
+}
+
+/**
+ * Remove a Testbed from the registry
+ * @param id  ID of the Testbed
+ */
+fn unregister(id: usize) -> Option<Registration> {
+    let mut registry = REGISTRY.lock().ok()?;
+    let idx = registry.iter().position(|registration| registration.id == id)?;
+    Some(registry.remove(idx))
+}
+
+/**
+ * Try to claim a name prefix for this process via a lock file
+ * The file is flock()ed and kept open, the lock goes away with the
+ * process (and its children) so there are no stale locks to take over
+ * @param prefix  Prefix to claim
+ */
+fn claim_prefix(prefix: &str) -> Result<Option<File>, String> {
+    fs::create_dir_all(LOCK_DIR)
+        .map_err(|e| format!("Failed to create lock directory {}: {}", LOCK_DIR, e))?;
+    let lock = PathBuf::from(LOCK_DIR).join(format!("{}.lock", prefix));
+
+    // lock files are never removed, an unlinked file could be locked
+    // by one process while another one creates and locks a new one
+    let mut file = OpenOptions::new().write(true).create(true).truncate(false).open(&lock)
+        .map_err(|e| format!("Failed to open lock {}: {}", lock.display(), e))?;
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+        let e = std::io::Error::last_os_error();
+        if e.kind() == ErrorKind::WouldBlock {
+            return Ok(None);
+        }
+        return Err(format!("Failed to lock {}: {}", lock.display(), e));
+    }
+
+    // owner for humans looking at the lock directory
+    file.set_len(0)
+        .and_then(|_| write!(file, "{}", std::process::id()))
+        .map_err(|e| format!("Failed to write lock {}: {}", lock.display(), e))?;
+    Ok(Some(file))
+}
+
+/**
+ * Claim the first free auto generated prefix
+ */
+fn allocate_prefix() -> Result<(String, File), String> {
+    for n in 0..MAX_INSTANCES {
+        let prefix = format!("{}{}", AUTO_PREFIX, n);
+        if let Some(lock) = claim_prefix(&prefix)? {
+            return Ok((prefix, lock));
+        }
+    }
+    Err(format!("No free testbed prefix, {} instances running?", MAX_INSTANCES))
+}
+
+/**
+ * Get the n-th host address of a subnet
+ * @param subnet  Subnet in CIDR notation e.g. 10.0.0.0/24
+ * @param n       Host number (1 for the first host)
+ */
+fn subnet_host(subnet: &str, n: u32) -> Result<String, String> {
+    let (addr, prefix_len) = parse_cidr(subnet)?;
+    let outside = || format!("Host {} is outside of subnet {}", n, subnet);
+    // host bits of the given address are ignored
+    let host = match addr {
+        IpAddr::V4(addr) => {
+            if prefix_len > 30 {
+                return Err(format!("Subnet {} too small for two hosts", subnet));
             }
+            let mask = u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0);
+            let network = u32::from(addr) & mask;
+            let host = network.checked_add(n)
+                .filter(|host| host & mask == network)
+                .ok_or_else(outside)?;
+            IpAddr::from(Ipv4Addr::from(host))
+        },
+        IpAddr::V6(addr) => {
+            if prefix_len > 126 {
+                return Err(format!("Subnet {} too small for two hosts", subnet));
+            }
+            let mask = u128::MAX.checked_shl(128 - prefix_len as u32).unwrap_or(0);
+            let network = u128::from(addr) & mask;
+            let host = network.checked_add(n as u128)
+                .filter(|host| host & mask == network)
+                .ok_or_else(outside)?;
+            IpAddr::from(Ipv6Addr::from(host))
+        },
+    };
+    Ok(format!("{}/{}", host, prefix_len))
+}
+
+/**
+ * Terminate a child process and everything it spawned
+ * sends SIGTERM and falls back to SIGKILL if it doesn't exit in time
+ * @param pid  PID of the child (also its process group)
+ */
+fn kill_child(pid: i32) {
+    let pid = Pid::from_raw(pid);
+    if signal::killpg(pid, Signal::SIGTERM).is_err() {
+        // already gone
+        return;
+    }
+
+    for _ in 0..50 {
+        match waitpid(pid, Some(WaitPidFlag::WNOHANG)) {
+            Ok(WaitStatus::StillAlive) => sleep(Duration::from_millis(100)),
+            _ => return,
         }
+    }
+
+    eprintln!("[testbed] Child {} did not exit - killing it", pid);
+    let _ = signal::killpg(pid, Signal::SIGKILL);
+    let _ = waitpid(pid, None);
+}
+
+/**
+ * Tear down everything of a Testbed
+ * errors are only printed so the remaining steps still run
+ * @param registration  Registration of the Testbed
+ */
+fn teardown(registration: Registration) {
+    println!("[testbed] Tearing down {} and {}", registration.ns1, registration.ns2);
+
+    // stop child processes first so nothing uses the interfaces anymore
+    for pid in registration.children {
+        kill_child(pid);
+    }
+
+    for (ns_name, if_name) in [
+        (&registration.ns1, &registration.if1),
+        (&registration.ns2, &registration.ifb2)
+    ] {
+        let ns = match NetNs::get(ns_name) {
+            Ok(ns) => ns,
+            Err(_) => continue,
+        };
+
+        // removing one end of the veth pair also removes the other
+        if let Err(e) = in_ns(&ns, |handle| link_teardown(handle, if_name.clone())) {
+            eprintln!("[testbed] {}", e);
+        }
+
+        // remove namespace
+        if let Err(e) = ns.remove() {
+            eprintln!("[testbed] Removing {} failed: {}", ns_name, e);
+        }
+    }
+
+    // release the prefix
+    drop(registration.lock);
+}
+
+impl Testbed {
+    pub fn new(config: &TestbedConfig) -> Result<Testbed, String> {
+        install_signal_handler();
+
+        // claim a prefix so parallel instances don't clash
+        let (prefix, lock) = match &config.prefix {
+            Some(prefix) => {
+                let lock = claim_prefix(prefix)?
+                    .ok_or(format!("Testbed prefix {} is in use by another instance", prefix))?;
+                (prefix.clone(), lock)
+            },
+            None => allocate_prefix()?,
+        };
+        println!("[testbed] Using prefix {}", prefix);
+
+        let ns1 = format!("{}-ns1", prefix);
+        let ns2 = format!("{}-ns2", prefix);
+        let if1 = format!("{}-{}", prefix, config.if1);
+        let if2 = format!("{}-{}", prefix, config.if2);
+        let ifb2 = format!("{}-{}", prefix, config.ifb2);
+
+        // from here on a failed setup is torn down
+        let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+        REGISTRY.lock()
+            .map_err(|e| format!("Testbed registry poisoned: {}", e))?
+            .push(Registration {
+                id,
+                ns1: ns1.clone(),
+                ns2: ns2.clone(),
+                if1: if1.clone(),
+                ifb2: ifb2.clone(),
+                lock,
+                children: Vec::new(),
+            });
+
+        let prepare = || -> Result<([String; 4], NetNs, NetNs), String> {
+            let addrs = [
+                subnet_host(config.subnet.as_str(), 1)?,
+                subnet_host(config.subnet.as_str(), 2)?,
+                subnet_host(config.subnet6.as_str(), 1)?,
+                subnet_host(config.subnet6.as_str(), 2)?,
+            ];
+            if !addrs[0].contains('.') || !addrs[2].contains(':') {
+                return Err(format!("Subnets {} and {} must be IPv4 and IPv6",
+                        config.subnet, config.subnet6));
+            }
+            for if_name in [&if1, &if2, &ifb2] {
+                if if_name.len() > IFNAMSIZ {
+                    return Err(format!("Interface name {} longer than {} characters",
+                            if_name, IFNAMSIZ));
+                }
+            }
+
+            // we hold the prefix so namespaces with it
+            // are leftovers of a previous run
+            for name in [&ns1, &ns2] {
+                let ns = NetNs::get(name);
+                if ns.is_ok() {
+                    println!("[testbed] {} exists - recreating", name);
+                    let _ = ns.unwrap().remove();
+                }
+            }
+
+            let ns1 = NetNs::new(&ns1)
+                .map_err(|e| format!("Creating {} failed: {}", ns1, e))?;
+            let ns2 = NetNs::new(&ns2)
+                .map_err(|e| format!("Creating {} failed: {}", ns2, e))?;
+            Ok((addrs, ns1, ns2))
+        };
+        let ([addr1, addr2, addr1_6, addr2_6], ns1, ns2) = prepare().inspect_err(|_| {
+            if let Some(registration) = unregister(id) {
+                teardown(registration);
+            }
+        })?;
 
         // create new testbed
-        let new = Self {
-            ns1: NetNs::new("ns1").expect("Creating ns1 failed"),
-            ns2: NetNs::new("ns2").expect("Createing ns2 failed"),
-            if1: String::from("veth1"),
-            if2: String::from("veth2"),
-            ifb2: String::from("ifb2"),
-            addr1: String::from("10.0.0.1/24"),
-            addr2: String::from("10.0.0.2/24"),
+        let mut new = Self {
+            ns1,
+            ns2,
+            if1,
+            if2,
+            ifb2,
+            addr1,
+            addr2,
+            addr1_6,
+            addr2_6,
+            ipv6: config.ipv6,
+            congestion_control: String::new(),
+            id,
+            owner: std::process::id(),
         };
-        
+
         // delete interfaces if they exist then create new ones
-        for if_name in [new.if1.as_str(), new.if2.as_str(), new.ifb2.as_str()] {
-            let if_status = Command::new("ip")
-                .args(["link", "show", "dev", if_name])
-                .stdout(Stdio::null())
-                .stderr(Stdio::null())
-                .status();
-            
-            if if_status.unwrap().success() {
-                println!("[testbed] Removing existing interface {}", if_name);
-                let _ = Command::new("ip")
-                    .args(["link", "delete", "dev", if_name])
-                    .status();
+        println!("[testbed] Creating new interfaces in network namespaces");
+        let new_ref = &new;
+        in_current_ns(|handle| async move {
+            let new = new_ref;
+            for if_name in [&new.if1, &new.if2, &new.ifb2] {
+                link_delete_if_exists(handle.clone(), if_name.clone()).await?;
             }
-        }
 
-        println!("[testbed] Creating new interfaces in network namespaces");
-        let _ = Command::new("ip")
-            .args([
-                "link", "add", "dev", new.if1.as_str(),
-                "netns", &new.ns1.path().file_name().unwrap().to_str().unwrap(),
-                "type", "veth",
-                "peer", "name", new.if2.as_str(),
-                "netns", &new.ns2.path().file_name().unwrap().to_str().unwrap(),
-            ])
-            .status();
+            handle.link().add().veth(new.if1.clone(), new.if2.clone())
+                .execute().await
+                .map_err(|e| format!("Failed to create veth pair {} {}: {}",
+                        new.if1, new.if2, e))?;
+
+            link_set_ns(handle.clone(), new.if1.clone(), &new.ns1).await?;
+            link_set_ns(handle.clone(), new.if2.clone(), &new.ns2).await
+        })?;
 
         // finally set interfaces UP
-        for spec in [
-            &(new.if1.as_str(), &new.ns1, new.addr1.as_str()), 
-            &(new.if2.as_str(), &new.ns2, new.addr2.as_str())
-        ] {
-            let if_name = &spec.0;
-            let ns = &spec.1;
-            let addr = &spec.2;
-            let _ = ns.run(|_| {
-                // setup address
-                let _ = Command::new("ip")
-                    .args(["addr", "add", addr,
-                        "dev", if_name])
-                    .status();
-
-                // set UP
-                let _ = Command::new("ip")
-                    .args(["link", "set", "dev", if_name, "up"])
-                    .status();
-            });
-        }
+        in_ns(&new.ns1, |handle| link_setup(handle, new.if1.clone(),
+                vec![new.addr1.clone(), new.addr1_6.clone()]))?;
+        in_ns(&new.ns2, |handle| link_setup(handle, new.if2.clone(),
+                vec![new.addr2.clone(), new.addr2_6.clone()]))?;
 
         // since qdiscs only affect outgoing traffic we need this bridge device
         // to add netem to incoming traffic
         println!("[testbed] Setting up ifb interface {} to handle incoming traffic for {}",
             new.ifb2.as_str(), new.if2.as_str());
-        let _ = Command::new("ip").args([
-                "link", "add", "name", new.ifb2.as_str(),
-                "netns", &new.ns2.path().file_name().unwrap().to_str().unwrap(),
-                "type", "ifb"
-            ]).status();
-        let _ = new.ns2.run(|_| {
-            let _ = Command::new("ip").args([
-                    "link", "set", "dev", new.ifb2.as_str(), "up"
-                ]).status();
-            // redirect incoming traffic through ifb2
-            let _ = Command::new("tc").args([
-                    "qdisc", "add", "dev", new.if2.as_str(), "ingress"
-                ]).status();
-            let _ = Command::new("tc").args([
-                    "filter", "add", "dev", new.if2.as_str(), "parent", "ffff:",
-                    "protocol", "ip", "u32", "match", "u32", "0", "0", "flowid", "1:1",
-                    "action", "mirred", "egress", "redirect", "dev", new.ifb2.as_str()
-                ]).status();
-        });
+        in_ns(&new.ns2, |handle| ifb_setup(handle, new.if2.clone(), new.ifb2.clone()))?;
+
+        // the sysctl is per namespace so server and client need it
+        if let Some(name) = &config.congestion_control {
+            set_congestion_control(&new.ns1, name)?;
+            set_congestion_control(&new.ns2, name)?;
+        }
+        new.congestion_control = get_congestion_control(&new.ns2)?;
+        println!("[testbed] Using TCP congestion control {}", new.congestion_control);
 
         // return Testbed
-        new
+        Ok(new)
     }
 
-    // destroy this Testbed (clean up namespaces and interfaces)
-    pub fn destroy(&self) {
-        for spec in [
-            (self.if1.as_str(), &self.ns1, self.addr1.as_str()), 
-            (self.if2.as_str(), &self.ns2, self.addr2.as_str())
-        ] {
-            let if_name = &spec.0;
-            let ns = &spec.1;
-            let addr = &spec.2;
-            let _ = ns.run(|_| {
-                // remove qdisc
-                let _ = Command::new("tc")
-                    .args(["qdisc", "delete", "dev", if_name, "root"])
-                    .status();
-
-                // set interface DOWN
-                let _ = Command::new("ip")
-                    .args(["link", "set", "dev", if_name, "down"])
-                    .status();
-
-                // remove address
-                let _ = Command::new("ip")
-                    .args(["addr", "delete", addr,
-                        "dev", if_name])
-                    .status();
-            });
+    /**
+     * Host part for URLs pointing to the server in ns1
+     * uses IPv6 (in brackets) if the Testbed was configured so
+     */
+    pub fn server_host(&self) -> String {
+        if self.ipv6 {
+            format!("[{}]", self.addr1_6.split("/").next().unwrap())
+        } else {
+            String::from(self.addr1.split("/").next().unwrap())
+        }
+    }
+
+    /**
+     * Host part for addresses pointing to the client in ns2
+     * same rules as server_host()
+     */
+    pub fn client_host(&self) -> String {
+        if self.ipv6 {
+            format!("[{}]", self.addr2_6.split("/").next().unwrap())
+        } else {
+            String::from(self.addr2.split("/").next().unwrap())
+        }
+    }
+
+    /**
+     * Fork a child process owned by this Testbed
+     * It gets terminated when the Testbed is torn down
+     * @param name  Name of the child for log messages
+     * @param f     Function run in the child
+     */
+    pub fn fork_child<F: FnOnce()>(&self, name: &str, f: F) -> Result<i32, String> {
+        match fork() {
+            Ok(Fork::Child) => {
+                // the parent's signal handler doesn't exist in the child
+                // restore the defaults so SIGTERM/SIGINT work again
+                for sig in [Signal::SIGINT, Signal::SIGTERM, Signal::SIGHUP] {
+                    let _ = unsafe { signal::signal(sig, SigHandler::SigDfl) };
+                }
+                // own process group so Ctrl-C only reaches the parent
+                // and killpg() takes down anything the child spawns
+                let _ = setpgid(Pid::from_raw(0), Pid::from_raw(0));
+
+                let result = std::panic::catch_unwind(AssertUnwindSafe(f));
+                exit(if result.is_ok() { 0 } else { 1 });
+            }
+            Ok(Fork::Parent(child)) => {
+                println!("[testbed] Spawned {} process with pid: {}", name, child);
+                // also set here to avoid racing the child
+                let _ = setpgid(Pid::from_raw(child), Pid::from_raw(child));
+                if let Ok(mut registry) = REGISTRY.lock() {
+                    if let Some(registration) = registry.iter_mut()
+                        .find(|registration| registration.id == self.id) {
+                        registration.children.push(child);
+                    }
+                }
+                Ok(child)
+            },
+            Err(_) => Err(format!("Spawning {} failed!", name)),
+        }
+    }
+}
+
+impl Drop for Testbed {
+    /**
+     * Destroy this Testbed (child processes, interfaces and namespaces)
+     * Forked children carry a copy of the Testbed so only the
+     * process that created it tears it down
+     */
+    fn drop(&mut self) {
+        if std::process::id() != self.owner {
+            return;
+        }
 
-            // remove interface
-            let _ = Command::new("ip")
-                .args(["link", "delete", if_name])
-                .status();
-            
-            // remove namespace
-            //ns.remove();
+        if let Some(registration) = unregister(self.id) {
+            teardown(registration);
         }
     }
 }
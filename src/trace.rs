@@ -1,120 +1,469 @@
-use csv::Reader;
+use serde::Serialize;
 use std::fs::File;
+use std::time::Instant;
 use std::str::FromStr;
 
 use crate::rtnetlink_utils::get_interface_id_by_name;
 use crate::rtnetlink_utils::qdisc_netem;
 use crate::rtnetlink_utils::get_distribution;
+use crate::capture::PacketCapture;
+use crate::metrics::{unix_time, MetricsWriter};
+use crate::netem::{LossModel, NetemConfig};
 
 
 #[derive(Clone)]
 struct TraceEvent {
     timestamp: f32,
-    loss: u32,
+    loss: LossModel,
     latency: i64,
-    jitter: i64
+    jitter: i64,
+    rate: u64
 }
 
 impl TraceEvent {
     /**
      * Create a new TraceEvent
      * @param timestamp  Reative timestamp on Trace
-     * @param loss       Loss in % (0-100)
+     * @param loss       Loss model
      * @param latency    Added latency in ns
      * @param jitter     Jitter on latency in ns
+     * @param rate       Rate limit in byte/s
      */
-    pub fn new(timestamp: f32, loss: u32, latency: i64, jitter: i64) -> Self {
-        Self { timestamp, loss, latency, jitter }
+    pub fn new(timestamp: f32, loss: LossModel, latency: i64, jitter: i64, rate: u64) -> Self {
+        Self { timestamp, loss, latency, jitter, rate }
+    }
+
+    /**
+     * Apply this event on top of a base netem config
+     * @param base  Base config with the settings that don't change
+     */
+    pub fn apply(&self, base: &NetemConfig) -> NetemConfig {
+        base.clone()
+            .loss(self.loss.clone())
+            .latency(self.latency)
+            .jitter(self.jitter)
+            .rate(self.rate)
+    }
+
+    /**
+     * Whether this event starts a full outage
+     */
+    fn is_outage(&self) -> bool {
+        matches!(self.loss, LossModel::Random { loss } if loss >= 100.0)
     }
 }
 
-struct Trace {
-    trace: Vec<TraceEvent>
+/**
+ * Direction of traffic as seen from ns2/the host
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// egress of the interface
+    Uplink,
+    /// egress of the ifb i.e. ingress of the interface
+    Downlink,
 }
 
-impl Trace {
+impl Direction {
     /**
-     * Create a new Trace from CSV file Reader
-     * @param rdr  CSV file reader
-     *
-     * Currently expects format:
-     * timestamp,lossTime
+     * Suffix of trace columns only applying to this direction
      */
-    pub fn new(rdr: &mut csv::Reader<File>) -> Result<Self, String> {
-        // CSV fields
-        const CSV_IDX_TIMESTAMP: usize = 0;
-        const CSV_IDX_LOSS_TIME: usize = 1;
-
-        // base loss for "clean" traffic
-        // paper says this is ~2% but that destroys download/upload tests
-        // because the TCP congestion control keeps decreasing the bandwidth
-        const BASE_LOSS: u32 = 0;
-
-        // currently unchanging latency and jitter
-        // during playback these will be doubled
-        // because they apply to both the egress (if) and ingress (ifb)
-        // taken from
-        // https://github.com/sys-uos/Starlink-on-the-Autobahn/blob/main/loss_emulation.py
-        const LATENCY: i64 = 18_000_000; // total 36 ms
-        const JITTER: i64 = 16_500_000; // total 33 ms
-
-        // trace vector
-        let mut trace: Vec<TraceEvent> = Vec::new();
-
-        // initial state
-        trace.push(TraceEvent::new(
-                0.0,
-                BASE_LOSS,
-                LATENCY,
-                JITTER
-        ));
-        
-        let mut iter = rdr.records();
-        let mut line = 1; // start at 1 due to header
-        while let Some(result) = iter.next() {
-            line += 1;
-            let record = result.unwrap();
-            let timestamp =
-                f32::from_str(&record[CSV_IDX_TIMESTAMP])
-                .map_err(|_| format!("Could not parse f32 from: {} on line {}",
-                        String::from(&record[CSV_IDX_TIMESTAMP]), line))?;
-            let loss_time =
-                f32::from_str(&record[CSV_IDX_LOSS_TIME])
-                .map_err(|_| format!("Could not parse f32 from: {} on line {}",
-                        String::from(&record[CSV_IDX_LOSS_TIME]), line))?;
+    fn column_suffix(&self) -> &'static str {
+        match self {
+            Direction::Uplink => "Up",
+            Direction::Downlink => "Down",
+        }
+    }
+}
+
+impl std::fmt::Display for Direction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Direction::Uplink => write!(f, "uplink"),
+            Direction::Downlink => write!(f, "downlink"),
+        }
+    }
+}
+
+/**
+ * Entry of the event log written during playback
+ */
+#[derive(Serialize)]
+struct AppliedEvent<'a> {
+    /// trace timestamp of the event in s
+    scheduled: f64,
+    /// seconds since the start of the trace when the request was sent
+    time: f64,
+    /// seconds since the unix epoch when the request was sent
+    unix_time: f64,
+    /// how late the request was sent in ms
+    drift: f64,
+    /// time until the kernel acknowledged the change in ms
+    netlink_latency: f64,
+    interface: &'a str,
+    direction: Direction,
+    /// "replaced" for the first event of a direction, "changed" afterwards
+    action: &'static str,
+    loss: String,
+    /// latency in ms
+    latency: f64,
+    /// jitter in ms
+    jitter: f64,
+    /// rate limit in mbit/s
+    rate: f64,
+}
+
+/**
+ * Column indices of a trace CSV file
+ * detected from its header
+ */
+struct TraceColumns {
+    timestamp: usize,
+    loss_time: Option<usize>,
+    loss: Option<usize>,
+    latency: Option<usize>,
+    jitter: Option<usize>,
+    rate: Option<usize>,
+}
+
+impl TraceColumns {
+    /**
+     * Detect columns from a CSV header
+     * Columns with the suffix of direction (e.g. rateDown) take
+     * precedence over the ones without
+     * If there is no "timestamp" column the header is assumed
+     * to be from the old positional format timestamp,lossTime
+     * @param header     Header record of the CSV file
+     * @param direction  Direction the columns are used for
+     */
+    pub fn from_header(header: &csv::StringRecord, direction: Direction) -> Self {
+        let find = |name: &str| header.iter().position(|field| field.trim() == name);
+        let find_directed = |name: &str|
+            find(format!("{}{}", name, direction.column_suffix()).as_str())
+            .or(find(name));
+
+        match find("timestamp") {
+            Some(timestamp) => Self {
+                timestamp,
+                loss_time: find_directed("lossTime"),
+                loss: find_directed("loss"),
+                latency: find_directed("latency"),
+                jitter: find_directed("jitter"),
+                rate: find_directed("rate"),
+            },
+            None => Self {
+                timestamp: 0,
+                loss_time: Some(1),
+                loss: None,
+                latency: None,
+                jitter: None,
+                rate: None,
+            },
+        }
+    }
+}
+
+/**
+ * Parse an optional field of a record
+ * Missing columns and empty fields yield None
+ * @param record  CSV record
+ * @param idx     Optional column index
+ * @param line    Line number for error messages
+ */
+fn parse_field<T: FromStr>(
+    record: &csv::StringRecord,
+    idx: Option<usize>,
+    line: usize
+) -> Result<Option<T>, String> {
+    let field = match idx.and_then(|idx| record.get(idx)) {
+        Some(field) if !field.trim().is_empty() => field.trim(),
+        _ => return Ok(None),
+    };
+
+    T::from_str(field)
+        .map(Some)
+        .map_err(|_| format!("Could not parse {} from: {} on line {}",
+                std::any::type_name::<T>(), String::from(field), line))
+}
+
+/**
+ * Everything a trace playback needs besides the interfaces
+ * built once by the runner
+ */
+pub struct Playback<'a> {
+    /// delay distribution file, defaults to /lib64/tc/pareto.dist
+    pub distribution_file: Option<String>,
+    /// base netem config the events are applied on
+    pub netem: NetemConfig,
+    /// point in time the trace timestamps are relative to
+    pub start: Instant,
+    /// file every applied event is logged to
+    pub event_log: Option<String>,
+    /// packet capture every applied event is noted in
+    pub capture: Option<&'a PacketCapture>,
+}
+
+/**
+ * A trace with separate events for uplink and downlink
+ */
+pub struct Trace {
+    uplink: Vec<TraceEvent>,
+    downlink: Vec<TraceEvent>,
+}
+
+/**
+ * Full outage of the trace (lossTime)
+ */
+#[derive(Clone, Debug)]
+pub struct Outage {
+    /// seconds since the start of the trace
+    pub start: f64,
+    /// seconds since the start of the trace
+    pub end: f64,
+    /// affects the uplink
+    pub uplink: bool,
+    /// affects the downlink
+    pub downlink: bool,
+}
+
+impl Outage {
+    /**
+     * Affected directions for reports
+     */
+    pub fn directions(&self) -> &'static str {
+        match (self.uplink, self.downlink) {
+            (true, true) => "both",
+            (true, false) => "uplink",
+            _ => "downlink",
+        }
+    }
+}
+
+/**
+ * Read the header and all records of a CSV file
+ * @param rdr  CSV file reader
+ */
+fn read_records(
+    rdr: &mut csv::Reader<File>
+) -> Result<(csv::StringRecord, Vec<csv::StringRecord>), String> {
+    let header = rdr.headers()
+        .map_err(|e| format!("Could not read CSV header: {}", e))?
+        .clone();
+
+    let mut records: Vec<csv::StringRecord> = Vec::new();
+    for (idx, result) in rdr.records().enumerate() {
+        let line = idx + 2; // 1 based and header
+        records.push(result
+            .map_err(|e| format!("Could not read record on line {}: {}", line, e))?);
+    }
+
+    Ok((header, records))
+}
+
+/**
+ * Turn CSV records into TraceEvents for one direction
+ * @param header     Header record of the CSV file
+ * @param records    Records of the CSV file
+ * @param direction  Direction the events are for
+ * @param base_loss  Loss outside of outages if the trace has none
+ */
+fn parse_events(
+    header: &csv::StringRecord,
+    records: &[csv::StringRecord],
+    direction: Direction,
+    base_loss: Option<&LossModel>
+) -> Result<Vec<TraceEvent>, String> {
+    // base loss for "clean" traffic
+    // paper says this is ~2% but that destroys download/upload tests
+    // because the TCP congestion control keeps decreasing the bandwidth
+    const BASE_LOSS: LossModel = LossModel::Random { loss: 0.0 };
+    let base_loss = base_loss.cloned().unwrap_or(BASE_LOSS);
+
+    // default latency and jitter
+    // during playback these will be doubled
+    // because they apply to both the egress (if) and ingress (ifb)
+    // taken from
+    // https://github.com/sys-uos/Starlink-on-the-Autobahn/blob/main/loss_emulation.py
+    const LATENCY: i64 = 18_000_000; // total 36 ms
+    const JITTER: i64 = 16_500_000; // total 33 ms
+    const RATE: u64 = 37_500_000; // 300 mbit/s
+
+    let columns = TraceColumns::from_header(header, direction);
+
+    // trace vector
+    let mut trace: Vec<TraceEvent> = Vec::new();
+
+    // initial state
+    trace.push(TraceEvent::new(
+            0.0,
+            base_loss.clone(),
+            LATENCY,
+            JITTER,
+            RATE
+    ));
+
+    for (idx, record) in records.iter().enumerate() {
+        let line = idx + 2; // 1 based and header
+        let timestamp = parse_field::<f32>(record, Some(columns.timestamp), line)?
+            .ok_or(format!("Missing timestamp on line {}", line))?;
+        let loss_time = parse_field::<f32>(record, columns.loss_time, line)?
+            .unwrap_or(0.0);
+        let loss = match columns.loss.and_then(|idx| record.get(idx)) {
+            Some(field) if !field.trim().is_empty() => LossModel::from_str(field)
+                .map_err(|e| format!("{} on line {}", e, line))?,
+            _ => base_loss.clone(),
+        };
+        // ms -> ns
+        let latency = parse_field::<f64>(record, columns.latency, line)?
+            .map(|latency| (latency * 1_000_000.0) as i64)
+            .unwrap_or(LATENCY);
+        let jitter = parse_field::<f64>(record, columns.jitter, line)?
+            .map(|jitter| (jitter * 1_000_000.0) as i64)
+            .unwrap_or(JITTER);
+        // mbit/s -> byte/s
+        let rate = parse_field::<f64>(record, columns.rate, line)?
+            .map(|rate| (rate * 1_000_000.0 / 8.0) as u64)
+            .unwrap_or(RATE);
 
+        if loss_time > 0.0 {
             // loss start
             trace.push(TraceEvent::new(
                     timestamp,
-                    100, // 100% loss
-                    LATENCY,
-                    JITTER
-            ));
-            // loss end
-            trace.push(TraceEvent::new(
-                    timestamp + loss_time,
-                    BASE_LOSS,
-                    LATENCY,
-                    JITTER
+                    LossModel::Random { loss: 100.0 },
+                    latency,
+                    jitter,
+                    rate
             ));
         }
+        // loss end or plain parameter change
+        trace.push(TraceEvent::new(
+                timestamp + loss_time,
+                loss,
+                latency,
+                jitter,
+                rate
+        ));
+    }
+
+    Ok(trace)
+}
 
-        Ok(Self { trace })
+impl Trace {
+    /**
+     * Create a new Trace from CSV file Reader
+     * @param rdr        CSV file reader
+     * @param base_loss  Loss outside of outages where the trace has none
+     *                   defaults to 0%
+     *
+     * Expects a header with the columns:
+     * timestamp  Start of the event in s (required)
+     * lossTime   Duration of a full outage in s
+     * loss       Loss outside of the outage
+     *            either in % (0-100) or a tc-netem(8) style loss model
+     *            e.g. "gemodel 1 10 70 0.1" or "state 1 20 5 30 0.1"
+     * latency    Added latency in ms
+     * jitter     Jitter on latency in ms
+     * rate       Rate limit in mbit/s
+     *
+     * Each column except timestamp can be given per direction
+     * by adding the suffix Up or Down e.g. rateUp,rateDown
+     * Missing columns or empty fields fall back to the defaults.
+     * The old format timestamp,lossTime is still accepted.
+     */
+    pub fn new(
+        rdr: &mut csv::Reader<File>,
+        base_loss: Option<LossModel>
+    ) -> Result<Self, String> {
+        let (header, records) = read_records(rdr)?;
+
+        Ok(Self {
+            uplink: parse_events(&header, &records, Direction::Uplink, base_loss.as_ref())?,
+            downlink: parse_events(&header, &records, Direction::Downlink, base_loss.as_ref())?,
+        })
+    }
+
+    /**
+     * Create a new Trace from separate CSV files per direction
+     * same format as Trace::new()
+     * @param uplink_rdr    CSV file reader for the uplink
+     * @param downlink_rdr  CSV file reader for the downlink
+     * @param base_loss     Loss outside of outages where the traces have none
+     */
+    pub fn new_asymmetric(
+        uplink_rdr: &mut csv::Reader<File>,
+        downlink_rdr: &mut csv::Reader<File>,
+        base_loss: Option<LossModel>
+    ) -> Result<Self, String> {
+        let (uplink_header, uplink_records) = read_records(uplink_rdr)?;
+        let (downlink_header, downlink_records) = read_records(downlink_rdr)
+            .map_err(|e| format!("Downlink trace: {}", e))?;
+
+        Ok(Self {
+            uplink: parse_events(&uplink_header, &uplink_records, Direction::Uplink,
+                base_loss.as_ref())?,
+            downlink: parse_events(&downlink_header, &downlink_records, Direction::Downlink,
+                base_loss.as_ref())
+                .map_err(|e| format!("Downlink trace: {}", e))?,
+        })
+    }
+
+    /**
+     * Full outages of both directions sorted by start
+     * overlapping outages are merged into one
+     */
+    pub fn outages(&self) -> Vec<Outage> {
+        let mut outages: Vec<Outage> = Vec::new();
+        for (direction, events) in [
+            (Direction::Uplink, &self.uplink),
+            (Direction::Downlink, &self.downlink),
+        ] {
+            // an outage lasts until the next event
+            for pair in events.windows(2).filter(|pair| pair[0].is_outage()) {
+                outages.push(Outage {
+                    start: pair[0].timestamp as f64,
+                    end: pair[1].timestamp as f64,
+                    uplink: direction == Direction::Uplink,
+                    downlink: direction == Direction::Downlink,
+                });
+            }
+        }
+        outages.sort_by(|a, b| a.start.total_cmp(&b.start));
+
+        let mut merged: Vec<Outage> = Vec::new();
+        for outage in outages {
+            match merged.last_mut() {
+                Some(last) if outage.start <= last.end => {
+                    last.end = last.end.max(outage.end);
+                    last.uplink |= outage.uplink;
+                    last.downlink |= outage.downlink;
+                },
+                _ => merged.push(outage),
+            }
+        }
+        merged
     }
 
     /**
      * Run a Trace
-     * @param distribution_file  Optional path to a distribution file
-     *                           Defaults to /lib64/tc/pareto.dist
-     * @param interface          Interface where trace should run
-     * @param ifb                Intermediate Function Block attached to interface
+     * @param playback   Netem settings, clock and outputs of the playback
+     * @param interface  Interface where trace should run
+     *                   uplink events are applied here
+     * @param ifb        Intermediate Function Block attached to interface
+     *                   downlink events are applied here
      */
     pub async fn run(
         &mut self,
-        distribution_file: Option<String>,
+        playback: Playback<'_>,
         interface: String,
         ifb: Option<String>
     ) -> Result<(), String> {
+        let Playback { distribution_file, netem, start, event_log, capture } = playback;
+        let mut event_log = event_log
+            .map(|path| MetricsWriter::create(path.as_str()))
+            .transpose()?;
+
+
         // setup handle and connection for rtnetlink stuff
         let (connection, handle, _) = rtnetlink::new_connection().unwrap();
         tokio::spawn(connection);
@@ -123,88 +472,86 @@ impl Trace {
             distribution_file.unwrap_or(String::from("/lib64/tc/pareto.dist")))
             .await
             .expect("[trace] Failed to get distribution data");
+        let netem = netem.distribution(distribution);
 
         // get interface ids
         let if_id = get_interface_id_by_name(handle.clone(), interface.clone())
             .await.unwrap();
 
         let mut ifb_id: Option<u32> = None;
-        if let Some(ifb) = ifb {
+        if let Some(ifb) = &ifb {
             ifb_id = Some(get_interface_id_by_name(handle.clone(), ifb.clone())
                 .await.unwrap());
         }
 
-        // constants for settings that don't change
-        // taken from
-        // https://github.com/sys-uos/Starlink-on-the-Autobahn/blob/main/loss_emulation.py
-        const LIMIT: u32 = 10_000; // pkts in queue
-        const RATE: u64 = 37_500_000; // 300 mbit/s
-
-        let start = tokio::time::Instant::now();
-        let mut iter = self.trace.iter();
-
-        // first event has to replace qdisc
-        if let Some(event) = iter.next() {
-            // outgoing traffic
-            qdisc_netem(
-                handle.clone(),
-                if_id,
-                false, // replace qdisc
-                LIMIT,
-                event.loss,
-                RATE,
-                event.latency,
-                event.jitter,
-                distribution.clone()
-            ).await.unwrap();
-
-            // incoming traffic
-            if let Some(ifb_id) = ifb_id {
-                qdisc_netem(
-                    handle.clone(),
-                    ifb_id,
-                    false, // replace qdisc
-                    LIMIT,
-                    event.loss,
-                    RATE,
-                    event.latency,
-                    event.jitter,
-                    distribution.clone()
-                ).await.unwrap();
-            }
+        // merge both directions into one timeline
+        // without an ifb only the uplink is played
+        let mut timeline: Vec<(Direction, &TraceEvent)> = self.uplink.iter()
+            .map(|event| (Direction::Uplink, event))
+            .collect();
+        if ifb_id.is_some() {
+            timeline.extend(self.downlink.iter()
+                .map(|event| (Direction::Downlink, event)));
         }
+        timeline.sort_by(|a, b| a.1.timestamp.total_cmp(&b.1.timestamp));
+
+        let start = tokio::time::Instant::from_std(start);
+        let mut uplink_started = false;
+        let mut downlink_started = false;
 
-        // other events
-        while let Some(event) = iter.next() {
+        for (direction, event) in timeline {
             let timestamp = tokio::time::Duration::from_secs_f32(event.timestamp);
             let _ = tokio::time::sleep_until(start + timestamp).await;
-            // outgoing traffic
-            let _ = qdisc_netem(
+            let config = event.apply(&netem);
+
+            // first event of a direction has to replace qdisc
+            let (id, name, started) = match direction {
+                // outgoing traffic
+                Direction::Uplink => (if_id, interface.as_str(), &mut uplink_started),
+                // incoming traffic
+                Direction::Downlink => (ifb_id.unwrap(), ifb.as_deref().unwrap(),
+                    &mut downlink_started),
+            };
+            let sent = tokio::time::Instant::now();
+            let sent_unix = unix_time();
+            qdisc_netem(
                 handle.clone(),
-                if_id,
-                true, // change qdisc
-                LIMIT,
-                event.loss,
-                RATE,
-                event.latency,
-                event.jitter,
-                distribution.clone()
+                id,
+                *started, // change or replace qdisc
+                &config
             ).await.unwrap();
+            let acked = tokio::time::Instant::now();
 
-            // incoming traffic
-            if let Some(ifb_id) = ifb_id {
-                qdisc_netem(
-                    handle.clone(),
-                    ifb_id,
-                    true, // change qdisc
-                    LIMIT,
+            if let Some(writer) = event_log.as_mut() {
+                let time = (sent - start).as_secs_f64();
+                writer.write(&AppliedEvent {
+                    scheduled: event.timestamp as f64,
+                    time,
+                    unix_time: sent_unix,
+                    drift: (time - event.timestamp as f64) * 1000.0,
+                    netlink_latency: (acked - sent).as_secs_f64() * 1000.0,
+                    interface: name,
+                    direction,
+                    action: if *started { "changed" } else { "replaced" },
+                    loss: event.loss.to_string(),
+                    latency: event.latency as f64 / 1_000_000.0,
+                    jitter: event.jitter as f64 / 1_000_000.0,
+                    rate: event.rate as f64 * 8.0 / 1_000_000.0,
+                })?;
+            }
+            if let Some(capture) = capture {
+                capture.comment(format!(
+                    "trace event at {:.3}s: {} {} on {} loss {} latency {}ms jitter {}ms rate {}mbit/s",
+                    event.timestamp,
+                    if *started { "changed" } else { "replaced" },
+                    direction,
+                    name,
                     event.loss,
-                    RATE,
-                    event.latency,
-                    event.jitter,
-                    distribution.clone()
-                ).await.unwrap();
+                    event.latency as f64 / 1_000_000.0,
+                    event.jitter as f64 / 1_000_000.0,
+                    event.rate as f64 * 8.0 / 1_000_000.0));
             }
+            *started = true;
         }
         
         println!("[trace] Reached end of trace");
@@ -214,17 +561,54 @@ impl Trace {
 }
 
 /**
- * Convenience function to create and run a trace
- * @param rdr        csv::Reader for the trace
+ * Convenience function to run a trace
+ * @param trace      Trace to run
+ * @param playback   Netem settings, clock and outputs of the playback
  * @param interface  Name of the interface the trace should run on
  * @param ifb        Intermediate Function Block attached to interface
  */ 
 pub async fn run_trace(
-    rdr: &mut Reader<File>,
-    distribution_file: Option<String>,
+    trace: &mut Trace,
+    playback: Playback<'_>,
     interface: String,
     ifb: Option<String>
 ) {
-    let mut trace = Trace::new(rdr).unwrap();
-    let _ = trace.run(distribution_file, interface, ifb).await;
+    if let Err(e) = trace.run(playback, interface, ifb).await {
+        eprintln!("[trace] {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /**
+     * Events alternating between an outage and no loss
+     * @param times  Start of every event, the first one is an outage
+     */
+    fn events(times: &[f32]) -> Vec<TraceEvent> {
+        times.iter().enumerate()
+            .map(|(idx, time)| TraceEvent::new(*time,
+                LossModel::Random { loss: if idx % 2 == 0 { 100.0 } else { 0.0 } }, 0, 0, 0))
+            .collect()
+    }
+
+    #[test]
+    fn outages_are_merged() {
+        let trace = Trace {
+            uplink: events(&[1.0, 3.0, 10.0, 12.0]),
+            // the last outage has no end and isn't counted
+            downlink: events(&[2.0, 5.0, 12.0, 14.0, 20.0, 21.0, 30.0]),
+        };
+        let outages: Vec<_> = trace.outages().iter()
+            .map(|outage| (outage.start, outage.end, outage.directions()))
+            .collect();
+        assert_eq!(outages, [
+            // overlapping
+            (1.0, 5.0, "both"),
+            // one starts where the other ends
+            (10.0, 14.0, "both"),
+            (20.0, 21.0, "downlink"),
+        ]);
+    }
 }
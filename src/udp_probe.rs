@@ -0,0 +1,256 @@
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::signal::unix::{signal, SignalKind};
+
+use crate::metrics::{unix_time, MetricsWriter, ThroughputRecorder};
+
+/// port the probe receiver listens on
+pub const PORT: u16 = 8001;
+
+/// default probe rate in mbit/s
+pub const DEFAULT_RATE: f64 = 10.0;
+
+/// default UDP payload size in bytes
+pub const DEFAULT_SIZE: usize = 1200;
+
+/**
+ * Header of every probe packet
+ * sequence number and send time in ns since the trace start
+ * both big endian, the rest of the packet is padding
+ */
+const HEADER_LEN: usize = 16;
+
+/**
+ * Largest UDP payload over IPv4
+ */
+const MAX_SIZE: usize = 65_507;
+
+/**
+ * Check probe settings
+ * @param rate  Rate in mbit/s
+ * @param size  UDP payload size in bytes
+ */
+pub fn validate(rate: f64, size: usize) -> Result<(), String> {
+    if !(rate > 0.0 && rate.is_finite()) {
+        return Err(format!("Probe rate {} must be greater than 0", rate));
+    }
+    if !(HEADER_LEN..=MAX_SIZE).contains(&size) {
+        return Err(format!("Probe packet size {} not in range [{}..{}]",
+                size, HEADER_LEN, MAX_SIZE));
+    }
+    Ok(())
+}
+
+/**
+ * Time between two probe packets
+ * @param rate  Rate in mbit/s
+ * @param size  UDP payload size in bytes
+ */
+pub fn interval(rate: f64, size: usize) -> Duration {
+    Duration::from_secs_f64(size as f64 * 8.0 / (rate * 1_000_000.0))
+}
+
+/**
+ * Send sequence numbered and timestamped packets at a constant rate
+ * runs until the process is killed
+ * @param target  Address of the receiver
+ * @param rate    Rate in mbit/s
+ * @param size    UDP payload size in bytes
+ * @param start   Start of the trace, send times are relative to it
+ */
+pub fn send(target: SocketAddr, rate: f64, size: usize, start: Instant) -> Result<(), String> {
+    let local: SocketAddr = match target {
+        SocketAddr::V4(_) => "0.0.0.0:0".parse().unwrap(),
+        SocketAddr::V6(_) => "[::]:0".parse().unwrap(),
+    };
+    let socket = UdpSocket::bind(local).map_err(|e| e.to_string())?;
+    socket.connect(target)
+        .map_err(|e| format!("Could not connect to {}: {}", target, e))?;
+
+    let interval = interval(rate, size);
+    println!("[udp_probe] Sending {} byte packets every {}us to {}",
+        size, interval.as_micros(), target);
+
+    // packets are sent on a fixed schedule so sleeping
+    // too long is made up for by the following packets
+    let first = Instant::now();
+    let mut packet = vec![0u8; size];
+    for seq in 0u64.. {
+        let due = first + interval.mul_f64(seq as f64);
+        let now = Instant::now();
+        if due > now {
+            std::thread::sleep(due - now);
+        }
+
+        packet[0..8].copy_from_slice(&seq.to_be_bytes());
+        packet[8..16].copy_from_slice(&(start.elapsed().as_nanos() as u64).to_be_bytes());
+        match socket.send(&packet) {
+            Ok(_) => (),
+            // full queue or ICMP errors of earlier packets
+            // the packet counts as lost
+            Err(e) if matches!(e.raw_os_error(),
+                Some(libc::ENOBUFS) | Some(libc::ECONNREFUSED)) => (),
+            Err(e) => return Err(format!("Sending to {} failed: {}", target, e)),
+        }
+    }
+    Ok(())
+}
+
+/**
+ * A single received probe packet
+ */
+#[derive(Serialize)]
+struct PacketSample {
+    seq: u64,
+    /// seconds since the start of the trace
+    sent: f64,
+    /// seconds since the start of the trace
+    received: f64,
+    /// seconds since the unix epoch
+    unix_time: f64,
+    /// one-way delay in ms
+    delay: f64,
+    /// UDP payload size in bytes
+    size: usize,
+    /// sequence numbers skipped since the highest one so far
+    gap: u64,
+    /// arrived after a higher sequence number
+    reordered: bool,
+    /// arrived before
+    duplicate: bool,
+    /// sequence numbers still missing i.e. lost so far
+    missing: usize,
+}
+
+/**
+ * Number of packets the sender was due to send up to now
+ * derived from its fixed schedule and the last received packet
+ * packets still in flight with the delay of that packet are not counted
+ * @param seq       Sequence number of the last received packet
+ * @param received  When it was received, since the start of the trace
+ * @param now       Time since the start of the trace
+ * @param interval  Time between two packets
+ */
+fn expected_packets(seq: u64, received: Duration, now: Duration, interval: Duration) -> u64 {
+    let sent_since = now.saturating_sub(received);
+    seq + 1 + (sent_since.as_secs_f64() / interval.as_secs_f64()) as u64
+}
+
+/**
+ * Receive probe packets and log every one of them
+ * runs until SIGTERM and prints a summary including the packets
+ * lost after the last one that was received
+ * Loss is counted from the first received packet on, the sender
+ * doesn't wait for the receiver so earlier ones may have been refused
+ * Has to be called inside a tokio runtime
+ * @param start       Start of the trace, the sender uses the same clock
+ * @param address     Address to listen on
+ * @param interval    Time between two packets of the sender
+ * @param recorder    Recorder counting the received bytes
+ * @param packet_log  Optional file every packet is logged to
+ */
+pub async fn receive(
+    start: Instant,
+    address: IpAddr,
+    interval: Duration,
+    recorder: ThroughputRecorder,
+    packet_log: Option<String>
+) -> Result<(), String> {
+    let mut writer = packet_log
+        .map(|path| MetricsWriter::create(path.as_str()))
+        .transpose()?;
+
+    let socket = tokio::net::UdpSocket::bind((address, PORT)).await
+        .map_err(|e| format!("Could not listen on port {}: {}", PORT, e))?;
+    println!("[udp_probe] Receiving on port {}", PORT);
+    let mut terminate = signal(SignalKind::terminate())
+        .map_err(|e| format!("Could not handle SIGTERM: {}", e))?;
+
+    let mut buf = vec![0u8; MAX_SIZE];
+    // first sequence number received and the one after the highest so far
+    let mut first_seq: Option<u64> = None;
+    let mut next_seq: u64 = 0;
+    let mut missing: BTreeSet<u64> = BTreeSet::new();
+    let mut total_packets: u64 = 0;
+    // sequence number and receive time of the highest packet so far
+    let mut last: Option<(u64, Duration)> = None;
+
+    let mut cur_time = SystemTime::now();
+    let mut cur_packets: u64 = 0;
+    loop {
+        let len = tokio::select! {
+            result = socket.recv(&mut buf) =>
+                result.map_err(|e| format!("Receiving failed: {}", e))?,
+            _ = terminate.recv() => break,
+        };
+        let received = start.elapsed();
+        if len < HEADER_LEN {
+            continue;
+        }
+        recorder.add(len as u64);
+
+        let seq = u64::from_be_bytes(buf[0..8].try_into().unwrap());
+        let sent = Duration::from_nanos(u64::from_be_bytes(buf[8..16].try_into().unwrap()));
+        if first_seq.is_none() {
+            first_seq = Some(seq);
+            next_seq = seq;
+        }
+
+        // everything between the highest sequence number and this one is missing
+        // until it shows up late
+        let (gap, reordered, duplicate) = if seq >= next_seq {
+            missing.extend(next_seq..seq);
+            let gap = seq - next_seq;
+            next_seq = seq + 1;
+            last = Some((seq, received));
+            (gap, false, false)
+        } else if missing.remove(&seq) {
+            (0, true, false)
+        } else {
+            (0, false, true)
+        };
+
+        if let Some(writer) = writer.as_mut() {
+            writer.write(&PacketSample {
+                seq,
+                sent: sent.as_secs_f64(),
+                received: received.as_secs_f64(),
+                unix_time: unix_time(),
+                delay: (received.as_secs_f64() - sent.as_secs_f64()) * 1000.0,
+                size: len,
+                gap,
+                reordered,
+                duplicate,
+                missing: missing.len(),
+            })?;
+        }
+
+        // every ~5 seconds print status
+        total_packets += 1;
+        cur_packets += 1;
+        let elapsed = cur_time.elapsed().unwrap();
+        if elapsed >= Duration::from_secs(5) {
+            println!("[udp_probe] Received {} packets in {}s, {} of {} missing",
+                cur_packets, elapsed.as_secs_f64(), missing.len(),
+                next_seq - first_seq.unwrap_or(0));
+            cur_time = SystemTime::now();
+            cur_packets = 0;
+        }
+    }
+
+    let Some((seq, received)) = last else {
+        println!("[udp_probe] No packets received");
+        return Ok(());
+    };
+    let expected = expected_packets(seq, received, start.elapsed(), interval);
+    let tail = expected - next_seq;
+    let sent_packets = expected - first_seq.unwrap_or(0);
+    let lost = missing.len() as u64 + tail;
+    println!("[udp_probe] Received {} packets from seq {}, {} of {} lost ({:.2} %), \
+        {} of them after the last received one",
+        total_packets, first_seq.unwrap_or(0), lost, sent_packets,
+        lost as f64 / sent_packets as f64 * 100.0, tail);
+    Ok(())
+}
@@ -1,65 +1,85 @@
 use futures::StreamExt;
-use std::time::{SystemTime, Duration};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::metrics::{MetricsConfig, ThroughputRecorder};
+use crate::player::{self, Manifest, Player};
+
+/// time before a failed request is tried again
+const RETRY_DELAY: Duration = Duration::from_millis(100);
 
 /**
  * Downloader that fetches a stream of data from url
  * printing stats about the transfer to the console
- * @param url  Url to download from
+ * Failed requests and broken transfers are started over
+ * @param url       Url to download from
+ * @param recorder  Recorder counting the received bytes
  */
-pub async fn download(url: String) -> Result<(), Box<dyn std::error::Error>> {
+pub async fn download(
+    url: String,
+    recorder: ThroughputRecorder
+) -> Result<(), Box<dyn std::error::Error>> {
     println!("[webclient] Downloading from {}", url);
+    let client = reqwest::Client::new();
 
-    let response = reqwest::Client::new()
-        .get(url)
-        .send()
-        .await
-        .unwrap();
+    let mut cur_time = Instant::now();
+    let mut cur_bytes: u64 = 0;
+    // the server might not be up yet and outages can break the connection
+    loop {
+        let response = match client.get(url.as_str()).send().await {
+            Ok(response) => response.error_for_status()?,
+            Err(e) => {
+                eprintln!("[webclient] Download request failed: {}", e);
+                tokio::time::sleep(RETRY_DELAY).await;
+                continue;
+            },
+        };
 
-    let mut stream = response.bytes_stream();
+        let mut stream = response.bytes_stream();
+        while let Some(item) = stream.next().await {
+            let chunk = match item {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    eprintln!("[webclient] Download failed: {}", e);
+                    break;
+                },
+            };
+            cur_bytes += chunk.len() as u64;
+            recorder.add(chunk.len() as u64);
 
-    let mut cur_time = SystemTime::now();
-    let mut cur_bytes: u64 = 0;
-    while let Some(item) = stream.next().await {
-        let chunk = item.expect("[webclient] Download failed");
-        cur_bytes += chunk.len() as u64;
-
-        // every ~5 seconds print status
-        let elapsed = cur_time.elapsed().unwrap();
-        if elapsed >= Duration::from_secs(5) {
-            // rate in mbit/s
-            let rate = ((((cur_bytes as f64) * 8.0) / 1000.0) / 1000.0) / elapsed.as_secs_f64();
-            println!("[webclient] Downloaded {} MB in {}s at rate {} Mbit/s",
-                cur_bytes / 1000 / 1000,
-                elapsed.as_secs_f64(),
-                rate);
-
-            // reset counters
-            cur_time = SystemTime::now();
-            cur_bytes = 0;
+            // every ~5 seconds print status
+            let elapsed = cur_time.elapsed();
+            if elapsed >= Duration::from_secs(5) {
+                // rate in mbit/s
+                let rate = ((((cur_bytes as f64) * 8.0) / 1000.0) / 1000.0) / elapsed.as_secs_f64();
+                println!("[webclient] Downloaded {} MB in {}s at rate {} Mbit/s",
+                    cur_bytes / 1000 / 1000,
+                    elapsed.as_secs_f64(),
+                    rate);
+
+                // reset counters
+                cur_time = Instant::now();
+                cur_bytes = 0;
+            }
         }
+        tokio::time::sleep(RETRY_DELAY).await;
     }
-
-    Ok(())
 }
 
 // 4MiB chunk size
 const CHUNK_SIZE: usize = 4 * 1024 * 1024;
 
 /**
- * Uploader that generates an infinte strem of data and POSTs it to url
+ * Infinite stream of chunks for an upload
  * printing stats about the transfer to the console
- * @param url  Url to upload to
  */
-pub async fn upload(url: String) -> Result<(), Box<dyn std::error::Error>> {
-    println!("[webclient] Uploading to {}", url);
-
-    // async stream generating an infinite amount chunks
-    let async_stream = async_stream::stream! {
-        let mut cur_time = SystemTime::now();
+fn upload_stream() -> impl futures::Stream<Item = Result<Vec<u8>, String>> {
+    async_stream::stream! {
+        let mut cur_time = Instant::now();
         let mut cur_bytes: u64 = 0;
         loop {
             // every ~5 seconds print status
-            let elapsed = cur_time.elapsed().unwrap();
+            let elapsed = cur_time.elapsed();
             if elapsed >= Duration::from_secs(5) {
                 // rate in mbit/s
                 let rate = ((((cur_bytes as f64) * 8.0) / 1000.0) / 1000.0) / elapsed.as_secs_f64();
@@ -69,21 +89,139 @@ pub async fn upload(url: String) -> Result<(), Box<dyn std::error::Error>> {
                     rate);
 
                 // reset counters
-                cur_time = SystemTime::now();
+                cur_time = Instant::now();
                 cur_bytes = 0;
             }
             let chunk = [255u8; CHUNK_SIZE];
             cur_bytes += chunk.len() as u64;
-            yield Ok::<Vec<u8>, String>(chunk.to_vec());
+            yield Ok(chunk.to_vec());
         }
-    };
+    }
+}
 
-    let _ = reqwest::Client::new()
-        .post(url)
-        .body(reqwest::Body::wrap_stream(async_stream))
+/**
+ * Uploader that generates an infinte strem of data and POSTs it to url
+ * The server counts the received bytes for the throughput samples
+ * Failed uploads are started over
+ * @param url  Url to upload to
+ */
+pub async fn upload(
+    url: String
+) -> Result<(), Box<dyn std::error::Error>> {
+    println!("[webclient] Uploading to {}", url);
+    let client = reqwest::Client::new();
+
+    // the server might not be up yet and outages can break the connection
+    // it only answers an upload that never ends if something went wrong
+    loop {
+        match client.post(url.as_str())
+            .body(reqwest::Body::wrap_stream(upload_stream()))
+            .send()
+            .await {
+            Ok(response) => {
+                response.error_for_status()?;
+            },
+            Err(e) => eprintln!("[webclient] Upload failed: {}", e),
+        }
+        tokio::time::sleep(RETRY_DELAY).await;
+    }
+}
+
+/**
+ * Download a single video segment
+ * @param client    HTTP client
+ * @param url       Url of the segment
+ * @param recorder  Recorder counting the received bytes
+ * @return size of the segment
+ */
+async fn fetch_segment(
+    client: &reqwest::Client,
+    url: &str,
+    recorder: &ThroughputRecorder
+) -> Result<u64, reqwest::Error> {
+    let response = client.get(url)
         .send()
-        .await
-        .unwrap();
+        .await?
+        .error_for_status()?;
+
+    let mut stream = response.bytes_stream();
+    let mut bytes: u64 = 0;
+    while let Some(item) = stream.next().await {
+        let chunk = item?;
+        bytes += chunk.len() as u64;
+        recorder.add(chunk.len() as u64);
+    }
+    Ok(bytes)
+}
+
+/**
+ * Adaptive streaming client that plays the synthetic video of the
+ * segment server, segment by segment with a simulated playout buffer
+ * Failed segments are requested again
+ * @param url          Base url of the video, the manifest is below it
+ * @param max_bitrate  Highest bitrate in kbit/s the player may pick
+ * @param buffer       Playout buffer the player fills up to in s
+ * @param start        Start of the trace, player times are relative to it
+ * @param recorder     Recorder counting the received bytes
+ * @param metrics      Metrics config with the player log
+ */
+pub async fn stream(
+    url: String,
+    max_bitrate: Option<u64>,
+    buffer: f64,
+    start: Instant,
+    recorder: ThroughputRecorder,
+    metrics: &MetricsConfig
+) -> Result<(), Box<dyn std::error::Error>> {
+    println!("[webclient] Streaming from {}", url);
+    let client = reqwest::Client::new();
+    let now = || start.elapsed().as_secs_f64();
+
+    // the server might not be up yet
+    // the startup delay counts from the request that got through
+    let (manifest, opened): (Manifest, f64) = loop {
+        let requested = now();
+        match client.get(format!("{}/manifest.json", url)).send().await {
+            Ok(response) => break (
+                serde_json::from_slice(&response.error_for_status()?.bytes().await?)?,
+                requested
+            ),
+            Err(e) => {
+                eprintln!("[webclient] Fetching manifest failed: {}", e);
+                tokio::time::sleep(RETRY_DELAY).await;
+            },
+        }
+    };
+    let player = Arc::new(Mutex::new(Player::new(manifest, max_bitrate, buffer, opened,
+        metrics.player_log_file.clone())?));
+    player::start_sampler(player.clone(), metrics, start)?;
+
+    for segment in 0u64.. {
+        // don't overfill the buffer
+        let wait = {
+            let mut player = player.lock().unwrap();
+            player.update(now())?;
+            player.wait_time()
+        };
+        if wait > 0.0 {
+            tokio::time::sleep(Duration::from_secs_f64(wait)).await;
+        }
+
+        let level = player.lock().unwrap().next_level(now())?;
+        let segment_url = format!("{}/{}/{}", url, level, segment);
+        let requested = now();
+        let bytes = loop {
+            match fetch_segment(&client, segment_url.as_str(), &recorder).await {
+                Ok(bytes) => break bytes,
+                Err(e) => {
+                    eprintln!("[webclient] Fetching segment {} failed: {}", segment, e);
+                    tokio::time::sleep(RETRY_DELAY).await;
+                },
+            }
+        };
+        let done = now();
+        player.lock().unwrap().segment_done(done, bytes, done - requested)?;
+    }
 
     Ok(())
 }
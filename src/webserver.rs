@@ -1,11 +1,31 @@
-use std::net::Ipv4Addr;
-use rocket::{get, post, routes};
+use std::net::IpAddr;
+use rocket::{get, post, routes, State};
 use rocket::response::stream::ByteStream;
 use rocket::data::{Data, ToByteUnit};
+use rocket::http::ContentType;
+use tokio::io::AsyncReadExt;
+
+use crate::metrics::ThroughputRecorder;
+use crate::player::Manifest;
+
+/// port the test server listens on
+pub const PORT: u16 = 8000;
 
 // 4MiB chunk size
 const CHUNK_SIZE: usize = 4 * 1024 * 1024;
 
+// read size for uploads, small so received bytes are counted as they arrive
+const READ_SIZE: usize = 64 * 1024;
+
+/// recorders counting the received bytes of the upload flows
+struct Uploads(Vec<ThroughputRecorder>);
+
+/// bitrate levels of the synthetic video in kbit/s
+const VIDEO_BITRATES: [u64; 6] = [500, 1_000, 2_500, 5_000, 8_000, 16_000];
+
+/// playback duration of a video segment in s
+const SEGMENT_DURATION: f64 = 2.0;
+
 /// generate an infinite stream of chunks as fast as possible
 /// rng would be nice but is too slow and keeps the thread at 100% cpu
 #[get("/infinite-data")]
@@ -18,25 +38,63 @@ fn infinite_data_get() -> ByteStream![[u8; CHUNK_SIZE]] {
 }
 
 /// accept an "infinite" stream of data
-#[post("/infinite-data", data =  "<data>")]
-async fn infinite_data_post(data: Data<'_>) -> Result<(), String> {
-    let _ = data.open(64.tibibytes())
-        .stream_to(tokio::io::sink()).await.map_err(|e| e.to_string())?;
-    Ok(())
+/// counting the received bytes of the flow if it has a recorder
+#[post("/infinite-data?<flow>", data =  "<data>")]
+async fn infinite_data_post(
+    data: Data<'_>,
+    flow: Option<usize>,
+    uploads: &State<Uploads>
+) -> Result<(), String> {
+    let recorder = uploads.0.get(flow.unwrap_or(0));
+    let mut stream = data.open(64.tibibytes());
+    let mut buf = vec![0u8; READ_SIZE];
+    loop {
+        let n = stream.read(&mut buf).await.map_err(|e| e.to_string())?;
+        if n == 0 {
+            return Ok(());
+        }
+        if let Some(recorder) = recorder {
+            recorder.add(n as u64);
+        }
+    }
+}
+
+/// describe the levels of the synthetic video
+#[get("/video/manifest.json")]
+fn video_manifest() -> (ContentType, String) {
+    let manifest = Manifest {
+        segment_duration: SEGMENT_DURATION,
+        bitrates: VIDEO_BITRATES.to_vec(),
+    };
+    (ContentType::JSON, serde_json::to_string(&manifest).unwrap())
+}
+
+/// synthetic video segment, as big as the level's bitrate
+/// times the segment duration, every segment exists
+#[get("/video/<level>/<segment>")]
+fn video_segment(level: usize, segment: u64) -> Option<Vec<u8>> {
+    let bitrate = VIDEO_BITRATES.get(level)?;
+    let size = (*bitrate as f64 * 1000.0 / 8.0 * SEGMENT_DURATION) as usize;
+    Some(vec![segment as u8; size])
 }
 
-/// setup and launch rocket
-pub async fn rocket_main() {
+/// setup and launch rocket on address
+/// uploads are recorders for the received bytes, one per upload flow
+pub async fn rocket_main(address: IpAddr, uploads: Vec<ThroughputRecorder>) -> Result<(), String> {
     let cfg = rocket::config::Config {
-        address: Ipv4Addr::new(0,0,0,0).into(),
+        address,
+        port: PORT,
         limits: rocket::data::Limits::default()
             .limit("data", 64.mebibytes()
         ),
         ..rocket::config::Config::default()
     };
 
-    let _ = rocket::custom(cfg)
-        .mount("/", routes![infinite_data_get,infinite_data_post])
+    rocket::custom(cfg)
+        .manage(Uploads(uploads))
+        .mount("/", routes![infinite_data_get,infinite_data_post,video_manifest,video_segment])
         .launch()
-        .await;
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("Web server failed: {}", e))
 }